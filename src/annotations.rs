@@ -0,0 +1,446 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Annotations for failed tests, independent of how they end up being reported.
+
+use crate::flaky::{flaky_tests, FlakyTest};
+use crate::source::{AnnotationTarget, Placement, SourceTree};
+use crate::template::{self, TemplateContext, Templates};
+use crate::{strip_ansi, FailureKind, TestFailureInfo, TestResult, TestResultValue, TestRun};
+use cargo_metadata::Package;
+use regex::Regex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnnotationLevel {
+    Failure,
+    Warning,
+    Notice,
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    /// The name of the package of the annotated test.
+    pub package: String,
+    /// The feature sets the annotated test failed with, identically.
+    pub feature_sets: Vec<Vec<String>>,
+    /// The name of the annotated test.
+    pub test: String,
+    pub level: AnnotationLevel,
+    /// Whether the test also passed with every one of the feature sets, which
+    /// makes the annotation a warning.
+    pub flaky: bool,
+    pub target: AnnotationTarget,
+    pub title: String,
+    pub message: String,
+    pub raw_details: String,
+}
+
+impl Annotation {
+    /// Whether this is the annotation of the test `test_name` of `test_run`.
+    pub fn is_for(&self, test_run: &TestRun, test_name: &str) -> bool {
+        self.package == test_run.package.name.as_str()
+            && self.feature_sets.contains(&test_run.features)
+            && self.test == test_name
+    }
+}
+
+/// A failed test, along with its annotation, if it got one.
+#[derive(Clone, Copy, Debug)]
+pub struct AnnotatedFailure<'a> {
+    pub test_run: &'a TestRun,
+    pub result: &'a TestResult,
+    pub failure: &'a TestFailureInfo,
+    pub is_doctest: bool,
+    /// What made the test fail.
+    pub kind: FailureKind,
+    pub annotation: Option<&'a Annotation>,
+}
+
+/// Pairs up every failed test and doctest in `test_runs` with its annotation.
+pub fn annotated_failures<'a>(
+    test_runs: &'a [TestRun],
+    annotations: &'a [Annotation],
+) -> impl Iterator<Item = AnnotatedFailure<'a>> {
+    test_runs.iter().flat_map(move |test_run| {
+        let tests = test_run.test_run.test_results.iter().map(|t| (t, false));
+        let doc_tests = test_run.doc_test_run.test_results.iter().map(|t| (t, true));
+        tests
+            .chain(doc_tests)
+            .filter_map(move |(result, is_doctest)| {
+                let TestResultValue::Failed(failure) = &result.result else {
+                    return None;
+                };
+                Some(AnnotatedFailure {
+                    test_run,
+                    result,
+                    failure,
+                    is_doctest,
+                    kind: failure.kind(is_doctest),
+                    annotation: annotations
+                        .iter()
+                        .find(|a| a.is_for(test_run, &result.name)),
+                })
+            })
+    })
+}
+
+/// Like [`annotated_failures`], but with only the first of the failures an
+/// annotation covers, for reports with one entry per annotation rather than
+/// per set of features.
+pub fn distinct_failures<'a>(
+    test_runs: &'a [TestRun],
+    annotations: &'a [Annotation],
+) -> impl Iterator<Item = AnnotatedFailure<'a>> {
+    let mut reported: Vec<&Annotation> = Vec::new();
+    annotated_failures(test_runs, annotations).filter(move |f| match f.annotation {
+        Some(annotation) if reported.iter().any(|r| std::ptr::eq(*r, annotation)) => false,
+        Some(annotation) => {
+            reported.push(annotation);
+            true
+        }
+        None => true,
+    })
+}
+
+/// Creates an annotation for every failed test and doctest in `test_runs`,
+/// placed where `source_tree` says GitHub can show it. A test that failed the
+/// same way with several feature sets gets a single annotation. The title,
+/// message and raw details are rendered with `templates`, where those are set.
+pub fn annotations(
+    test_runs: &[TestRun],
+    source_tree: &SourceTree,
+    templates: &Templates,
+) -> miette::Result<Vec<Annotation>> {
+    let environment = templates.environment()?;
+    let repo_paths = source_tree.repo_paths();
+    let mut failures = Vec::new();
+    for test_run in test_runs {
+        for result in &test_run.test_run.test_results {
+            let TestResultValue::Failed(failure) = &result.result else {
+                continue;
+            };
+            let reported = failure.location.as_ref().and_then(|l| {
+                let path = repo_paths.resolve(&test_run.package, &l.file)?;
+                Some(AnnotationTarget::new(path, l.line, Some(l.column)))
+            });
+            let Some(target) = place(source_tree, &test_run.package, result, false, reported)
+            else {
+                continue;
+            };
+
+            push_failure(&mut failures, test_run, result, failure, false, target);
+        }
+        for result in &test_run.doc_test_run.test_results {
+            let TestResultValue::Failed(failure) = &result.result else {
+                continue;
+            };
+            // A doctest named in an unexpected format is annotated in the
+            // source file of the package's library instead.
+            let reported = DOCTEST_NAME_FILE_REGEX
+                .with(|r| r.captures(&result.name))
+                .and_then(|c| {
+                    let file = c["file"].replace('\\', "/");
+                    let line: u64 = c["line"].parse().ok()?;
+                    let path = repo_paths.resolve(&test_run.package, &file)?;
+                    locate_in_doctest(source_tree, &test_run.package, &file, &path, line, failure)
+                        .or_else(|| Some(AnnotationTarget::new(path, line, None)))
+                });
+            let Some(target) = place(source_tree, &test_run.package, result, true, reported) else {
+                continue;
+            };
+
+            push_failure(&mut failures, test_run, result, failure, true, target);
+        }
+    }
+
+    let flaky_tests = flaky_tests(test_runs);
+    let mut annotations = Vec::new();
+    for PendingAnnotation {
+        mut annotation,
+        test_run,
+        failure,
+        is_doctest,
+    } in failures
+    {
+        let flaky: Vec<_> = annotation
+            .feature_sets
+            .iter()
+            .filter_map(|f| flaky_tests.get(&annotation.package, f, &annotation.test))
+            .collect();
+        // It's only a real failure if it failed every time with some feature
+        // set.
+        if flaky.len() == annotation.feature_sets.len() {
+            annotation.level = AnnotationLevel::Warning;
+            annotation.flaky = true;
+        }
+        let passed = passed_feature_sets(test_runs, &annotation);
+        let snippet = source_tree.snippet(&annotation.target, SNIPPET_CONTEXT);
+        annotation.message = failure_message(
+            &annotation.feature_sets,
+            &passed,
+            &flaky,
+            failure,
+            snippet.as_deref(),
+        );
+
+        let context = TemplateContext::new(
+            test_run,
+            &annotation.test,
+            is_doctest,
+            failure,
+            &annotation.target,
+            &annotation.feature_sets,
+            &passed,
+            &flaky,
+            snippet.as_deref(),
+        );
+        let title = template::render(&environment, "title", &context)?;
+        let message = template::render(&environment, "message", &context)?;
+        let raw_details = template::render(&environment, "raw-details", &context)?;
+        annotation.title = title.unwrap_or(annotation.title);
+        annotation.message = message.unwrap_or(annotation.message);
+        annotation.raw_details = raw_details.unwrap_or(annotation.raw_details);
+        annotations.push(annotation);
+    }
+
+    Ok(annotations)
+}
+
+/// Where the doctest whose code block opens on `line` of `file` (as named by
+/// the test, relative to the package) failed, if that's known. Merged
+/// doctests panic in a bundle of all of them, and `compile_fail` doctests
+/// that compiled have no location at all, so those are left to be annotated
+/// at the start of the doctest.
+fn locate_in_doctest(
+    source_tree: &SourceTree,
+    package: &Package,
+    file: &str,
+    path: &str,
+    line: u64,
+    failure: &TestFailureInfo,
+) -> Option<AnnotationTarget> {
+    let doctest = source_tree.doctest(path, line);
+    let Some(location) = &failure.location else {
+        // rustdoc reports errors compiling the doctest at the lines of the
+        // file, but at the columns of the doctest's code.
+        let (line, column) = strip_ansi(&failure.output).lines().find_map(|l| {
+            let c = COMPILE_ERROR_LOCATION_REGEX.with(|r| r.captures(l))?;
+            (c["file"].replace('\\', "/") == file)
+                .then(|| Some((c["line"].parse().ok()?, c["column"].parse().ok()?)))?
+        })?;
+        let column = doctest
+            .and_then(|d| d.locate_compiled(line, column))
+            .map(|(_, column)| column);
+        return Some(AnnotationTarget::new(path.to_owned(), line, column));
+    };
+    if location.file != file {
+        // It panicked in code the doctest called.
+        let path = source_tree.repo_paths().resolve(package, &location.file)?;
+        return Some(AnnotationTarget::new(
+            path,
+            location.line,
+            Some(location.column),
+        ));
+    }
+
+    let crate_name = package
+        .targets
+        .iter()
+        .find(|t| t.is_lib())
+        .map_or(package.name.as_str(), |t| t.name.as_str())
+        .replace('-', "_");
+    let (line, column) = doctest?.locate_standalone(&crate_name, location.line, location.column)?;
+    Some(AnnotationTarget::new(path.to_owned(), line, Some(column)))
+}
+
+/// A failure to annotate, before its message is written.
+struct PendingAnnotation<'a> {
+    annotation: Annotation,
+    test_run: &'a TestRun,
+    failure: &'a TestFailureInfo,
+    is_doctest: bool,
+}
+
+/// Adds the failure of `result` to `failures`, unless the test already failed
+/// the same way with other features, in which case those are extended.
+fn push_failure<'a>(
+    failures: &mut Vec<PendingAnnotation<'a>>,
+    test_run: &'a TestRun,
+    result: &TestResult,
+    failure: &'a TestFailureInfo,
+    is_doctest: bool,
+    target: AnnotationTarget,
+) {
+    let identical = failures.iter_mut().find(|f| {
+        f.annotation.package == test_run.package.name.as_str()
+            && f.annotation.test == result.name
+            && f.annotation.target == target
+            && f.failure.panic_text == failure.panic_text
+    });
+    match identical {
+        Some(PendingAnnotation { annotation, .. }) => {
+            if !annotation.feature_sets.contains(&test_run.features) {
+                annotation.feature_sets.push(test_run.features.clone());
+            }
+        }
+        None => failures.push(PendingAnnotation {
+            annotation: Annotation {
+                package: test_run.package.name.to_string(),
+                feature_sets: vec![test_run.features.clone()],
+                test: result.name.clone(),
+                level: AnnotationLevel::Failure,
+                flaky: false,
+                target,
+                title: result.name.clone(),
+                message: String::new(),
+                raw_details: raw_details(failure),
+            },
+            test_run,
+            failure,
+            is_doctest,
+        }),
+    }
+}
+
+/// The feature sets the test of `annotation` passed with.
+fn passed_feature_sets(test_runs: &[TestRun], annotation: &Annotation) -> Vec<Vec<String>> {
+    let mut passed: Vec<Vec<String>> = Vec::new();
+    for test_run in test_runs
+        .iter()
+        .filter(|r| r.package.name.as_str() == annotation.package)
+    {
+        let passed_here = test_run
+            .test_run
+            .test_results
+            .iter()
+            .chain(&test_run.doc_test_run.test_results)
+            .any(|t| t.name == annotation.test && matches!(t.result, TestResultValue::Ok));
+        if passed_here
+            && !annotation.feature_sets.contains(&test_run.features)
+            && !passed.contains(&test_run.features)
+        {
+            passed.push(test_run.features.clone());
+        }
+    }
+    passed
+}
+
+/// How many lines of source to show before and after the annotated line.
+const SNIPPET_CONTEXT: u64 = 2;
+
+fn failure_message(
+    feature_sets: &[Vec<String>],
+    passed_feature_sets: &[Vec<String>],
+    flaky_tests: &[&FlakyTest],
+    failure: &TestFailureInfo,
+    snippet: Option<&str>,
+) -> String {
+    let mut message = String::new();
+    if !flaky_tests.is_empty() {
+        message += &format!(
+            "flaky: failed {} of {} runs\n",
+            flaky_tests.iter().map(|t| t.failed).sum::<usize>(),
+            flaky_tests
+                .iter()
+                .map(|t| t.passed + t.failed)
+                .sum::<usize>()
+        );
+    }
+    message += &format!("features: {}\n", format_feature_sets(feature_sets));
+    if !passed_feature_sets.is_empty() {
+        message += &format!(
+            "passed with features: {}\n",
+            format_feature_sets(passed_feature_sets)
+        );
+    }
+    message += &format!(
+        "\ncause:\n{}\n\n",
+        failure.panic_text.replace("\r\n", "\n").replace('\r', "\n")
+    );
+    if let Some(snippet) = snippet {
+        message += &format!("source:\n{}\n\n", snippet);
+    }
+    message += &failure.stacktrace.replace("\r\n", "\n").replace('\r', "\n");
+    message
+}
+
+/// The output of the failed test, which GitHub shows when the annotation is
+/// expanded.
+fn raw_details(failure: &TestFailureInfo) -> String {
+    let output = strip_ansi(&failure.output);
+    match output.trim_end() {
+        "" => failure.panic_text.clone(),
+        output => output.to_owned(),
+    }
+}
+
+fn format_feature_sets(feature_sets: &[Vec<String>]) -> String {
+    feature_sets
+        .iter()
+        .map(|f| format!("[{}]", f.join(", ")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Finds a place in the checked-out source to annotate the failure of
+/// `test_name`, logging it when that's not where the test output said it
+/// failed.
+fn place(
+    source_tree: &SourceTree,
+    package: &Package,
+    result: &TestResult,
+    is_doctest: bool,
+    reported: Option<AnnotationTarget>,
+) -> Option<AnnotationTarget> {
+    let test_name = &result.name;
+    let original = reported.as_ref().map(ToString::to_string);
+    match source_tree.place(
+        package,
+        result.target.as_deref(),
+        test_name,
+        is_doctest,
+        reported,
+    ) {
+        Some((target, Placement::AsReported)) => Some(target),
+        Some((target, placement)) => {
+            eprintln!(
+                "note: annotating {} at {} ({}), since {}",
+                test_name,
+                target,
+                placement,
+                match original {
+                    Some(original) => format!("{} does not exist in the repository", original),
+                    None => "it failed without a location in the repository".to_owned(),
+                }
+            );
+            Some(target)
+        }
+        None => {
+            eprintln!(
+                "warning: found nowhere in the repository to annotate {}; not annotating it",
+                test_name
+            );
+            None
+        }
+    }
+}
+
+thread_local! {
+    static DOCTEST_NAME_FILE_REGEX: Regex = Regex::new(r"(?P<file>.+?) - (?:.+ )?\(line (?P<line>\d+)\)").unwrap();
+    static COMPILE_ERROR_LOCATION_REGEX: Regex = Regex::new(r"^\s*--> (?P<file>.+):(?P<line>\d+):(?P<column>\d+)$").unwrap();
+}
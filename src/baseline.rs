@@ -0,0 +1,106 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Comparison of test failures with those of a baseline, like the merge base.
+
+use crate::{TestResultValue, TestRun};
+
+/// How a failure compares to the baseline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureStatus {
+    /// The test didn't fail in the baseline.
+    New,
+    /// The test failed in the baseline too.
+    StillFailing,
+}
+
+/// A test that failed in the baseline.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BaselineFailure {
+    pub package: String,
+    pub features: Vec<String>,
+    pub name: String,
+}
+
+/// The failures of a baseline test run.
+#[derive(Clone, Debug, Default)]
+pub struct Baseline {
+    failures: Vec<BaselineFailure>,
+}
+
+impl Baseline {
+    pub fn new(test_runs: &[TestRun]) -> Self {
+        let mut failures = Vec::new();
+        for test_run in test_runs {
+            for result in test_run
+                .test_run
+                .test_results
+                .iter()
+                .chain(&test_run.doc_test_run.test_results)
+            {
+                let failure = BaselineFailure {
+                    package: test_run.package.name.to_string(),
+                    features: test_run.features.clone(),
+                    name: result.name.clone(),
+                };
+                if matches!(result.result, TestResultValue::Failed(_))
+                    && !failures.contains(&failure)
+                {
+                    failures.push(failure);
+                }
+            }
+        }
+
+        Self { failures }
+    }
+
+    /// How the failure of the test `test_name` with `features` compares to the
+    /// baseline.
+    pub fn status(&self, package: &str, features: &[String], test_name: &str) -> FailureStatus {
+        let failed = self
+            .failures
+            .iter()
+            .any(|f| f.package == package && f.features == features && f.name == test_name);
+        if failed {
+            FailureStatus::StillFailing
+        } else {
+            FailureStatus::New
+        }
+    }
+
+    /// The baseline failures of tests that passed in `test_runs`.
+    pub fn fixed(&self, test_runs: &[TestRun]) -> Vec<&BaselineFailure> {
+        self.failures
+            .iter()
+            .filter(|f| {
+                let mut results = test_runs
+                    .iter()
+                    .filter(|r| r.package.name.as_str() == f.package && r.features == f.features)
+                    .flat_map(|r| {
+                        r.test_run
+                            .test_results
+                            .iter()
+                            .chain(&r.doc_test_run.test_results)
+                    })
+                    .filter(|t| t.name == f.name)
+                    .peekable();
+                results.peek().is_some() && results.all(|t| matches!(t.result, TestResultValue::Ok))
+            })
+            .collect()
+    }
+}
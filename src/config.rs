@@ -0,0 +1,214 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Configuration from `.cargo-test-annotations.toml` in the workspace root, or
+//! from `[package.metadata.test-annotations]` in the manifest of a package.
+
+use crate::annotations::{Annotation, AnnotationLevel};
+use crate::template::Templates;
+use cargo_metadata::Metadata;
+use chrono::NaiveDate;
+use miette::{Context, IntoDiagnostic};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+pub const CONFIG_FILE_NAME: &str = ".cargo-test-annotations.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Tests whose failures are only warned about.
+    #[serde(default)]
+    pub quarantine: Vec<Quarantine>,
+    /// How to write the annotations of failed tests.
+    #[serde(default)]
+    pub templates: Templates,
+}
+
+/// Known-flaky or -broken tests, whose failures shouldn't fail the check.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quarantine {
+    /// A glob pattern matching the names of the tests.
+    pub test: TestPattern,
+    /// The package of the tests, or any package if not given.
+    pub package: Option<String>,
+    /// The last day the tests are quarantined.
+    pub expires: Option<Expiry>,
+    /// A link to the issue tracking the tests.
+    pub issue: Option<String>,
+}
+
+/// A glob pattern matching the names of tests, which is checked when it's
+/// read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestPattern(glob::Pattern);
+
+impl TestPattern {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn matches(&self, test_name: &str) -> bool {
+        self.0.matches(test_name)
+    }
+}
+
+/// The date a quarantine expires, written as a string or a TOML date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Expiry(pub NaiveDate);
+
+/// A quarantine that matched a failed test, but had expired.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpiredQuarantine {
+    pub test: String,
+    pub expired: NaiveDate,
+}
+
+impl Config {
+    /// Reads the configuration file of the workspace, if there is one, along
+    /// with the configuration in the metadata of each package, and checks that
+    /// the templates compile.
+    pub fn load(metadata: &Metadata) -> miette::Result<Self> {
+        let path = metadata.workspace_root.join(CONFIG_FILE_NAME);
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .into_diagnostic()
+                .with_context(|| path.to_string())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e).into_diagnostic().with_context(|| path.to_string()),
+        };
+
+        for package in metadata.workspace_packages() {
+            let Some(value) = package.metadata.get("test-annotations") else {
+                continue;
+            };
+            let package_config =
+                Config::deserialize(value)
+                    .into_diagnostic()
+                    .with_context(|| {
+                        format!(
+                            "{}: package.metadata.test-annotations",
+                            package.manifest_path
+                        )
+                    })?;
+            config
+                .quarantine
+                .extend(package_config.quarantine.into_iter().map(|q| Quarantine {
+                    package: q.package.or_else(|| Some(package.name.to_string())),
+                    ..q
+                }));
+            // Templates apply to the whole workspace, so a package can only
+            // set those the workspace doesn't.
+            config.templates = config.templates.or(package_config.templates);
+        }
+
+        config.templates.environment()?;
+        Ok(config)
+    }
+
+    /// Downgrades the annotations of quarantined tests to warnings, noting
+    /// why. Quarantines that expired before `today` no longer apply, and are
+    /// returned for the tests they would have applied to.
+    pub fn quarantine(
+        &self,
+        annotations: &mut [Annotation],
+        today: NaiveDate,
+    ) -> Vec<ExpiredQuarantine> {
+        let mut expired = Vec::new();
+        for annotation in annotations {
+            let Some(quarantine) = self.quarantine.iter().find(|q| q.matches(annotation)) else {
+                continue;
+            };
+            match quarantine.expires {
+                Some(Expiry(expires)) if expires < today => {
+                    expired.push(ExpiredQuarantine {
+                        test: annotation.test.clone(),
+                        expired: expires,
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+
+            annotation.level = annotation.level.max(AnnotationLevel::Warning);
+            let mut note = "quarantined".to_owned();
+            if let Some(Expiry(expires)) = quarantine.expires {
+                note += &format!(" until {}", expires);
+            }
+            if let Some(issue) = &quarantine.issue {
+                note += &format!(", see {}", issue);
+            }
+            annotation.message = format!("{}\n{}", note, annotation.message);
+        }
+        expired
+    }
+}
+
+impl Quarantine {
+    pub fn matches(&self, annotation: &Annotation) -> bool {
+        if self
+            .package
+            .as_ref()
+            .is_some_and(|p| *p != annotation.package)
+        {
+            return false;
+        }
+        self.test.matches(&annotation.test)
+    }
+}
+
+impl<'de> Deserialize<'de> for TestPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        glob::Pattern::new(&pattern).map(TestPattern).map_err(|e| {
+            de::Error::custom(format!("invalid test pattern `{}`: {}", pattern, e.msg))
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Expiry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExpiryVisitor;
+
+        impl<'de> Visitor<'de> for ExpiryVisitor {
+            type Value = Expiry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a date like 2024-12-31")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Expiry, E> {
+                NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                    .map(Expiry)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+
+            // TOML dates are deserialized as a map with a single, private key.
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Expiry, A::Error> {
+                let Some((_, date)) = map.next_entry::<String, String>()? else {
+                    return Err(de::Error::invalid_length(0, &self));
+                };
+                self.visit_str(&date)
+            }
+        }
+
+        deserializer.deserialize_any(ExpiryVisitor)
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Serialization of parsed test runs to and from a versioned JSON document.
+
+use crate::TestRun;
+use miette::{Diagnostic, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// The version of the dump format written by [`write`].
+///
+/// Bump this whenever the serialized shape of [`TestRun`] or any of the types
+/// it contains changes in a way that older readers can't handle.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The dump document, borrowing the test runs when writing and owning them
+/// when reading.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Dump<'a> {
+    pub schema_version: u32,
+    pub test_runs: Cow<'a, [TestRun]>,
+}
+
+impl<'a> Dump<'a> {
+    pub fn new(test_runs: impl Into<Cow<'a, [TestRun]>>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            test_runs: test_runs.into(),
+        }
+    }
+}
+
+pub fn write<W: Write>(w: W, test_runs: &[TestRun]) -> miette::Result<()> {
+    serde_json::to_writer_pretty(w, &Dump::new(test_runs)).into_diagnostic()
+}
+
+pub fn read<R: Read>(r: R) -> miette::Result<Vec<TestRun>> {
+    // The version is checked first, since a dump of another version may not
+    // deserialize at all.
+    let value: serde_json::Value =
+        serde_json::from_reader(std::io::BufReader::new(r)).into_diagnostic()?;
+    let schema_version = value.get("schema_version").and_then(|v| v.as_u64());
+    if schema_version != Some(u64::from(SCHEMA_VERSION)) {
+        return Err(DumpSchemaVersionError(schema_version).into());
+    }
+
+    let dump: Dump = serde_json::from_value(value).into_diagnostic()?;
+    Ok(dump.test_runs.into_owned())
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[error("Unsupported dump schema version: {} (expected {SCHEMA_VERSION})", .0.map_or("none".to_owned(), |v| v.to_string()))]
+pub struct DumpSchemaVersionError(pub Option<u64>);
@@ -0,0 +1,95 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Detection of tests that both passed and failed, across reruns or the legs
+//! of a build matrix.
+
+use crate::{TestResultValue, TestRun};
+use std::collections::HashMap;
+
+/// A test that passed and failed with the same package and features.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlakyTest {
+    pub package: String,
+    pub features: Vec<String>,
+    pub name: String,
+    /// How many times the test passed.
+    pub passed: usize,
+    /// How many times the test failed.
+    pub failed: usize,
+}
+
+/// The flaky tests of some test runs, by package, features and name.
+#[derive(Clone, Debug, Default)]
+pub struct FlakyTests {
+    tests: HashMap<(String, Vec<String>, String), FlakyTest>,
+}
+
+impl FlakyTests {
+    /// The test `name` of `package` with `features`, if it's flaky.
+    pub fn get(&self, package: &str, features: &[String], name: &str) -> Option<&FlakyTest> {
+        self.tests
+            .get(&(package.to_owned(), features.to_vec(), name.to_owned()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tests.is_empty()
+    }
+
+    /// The flaky tests, ordered by package, features and name.
+    pub fn iter(&self) -> impl Iterator<Item = &FlakyTest> {
+        let mut tests: Vec<_> = self.tests.iter().collect();
+        tests.sort_by_key(|(key, _)| *key);
+        tests.into_iter().map(|(_, test)| test)
+    }
+}
+
+/// Finds the tests and doctests in `test_runs` that both passed and failed.
+pub fn flaky_tests(test_runs: &[TestRun]) -> FlakyTests {
+    let mut tests: HashMap<_, FlakyTest> = HashMap::new();
+    for test_run in test_runs {
+        for result in test_run
+            .test_run
+            .test_results
+            .iter()
+            .chain(&test_run.doc_test_run.test_results)
+        {
+            let (passed, failed) = match result.result {
+                TestResultValue::Ok => (1, 0),
+                TestResultValue::Failed(_) => (0, 1),
+                TestResultValue::Ignored { .. } => continue,
+            };
+            let package = test_run.package.name.to_string();
+            let key = (package, test_run.features.clone(), result.name.clone());
+            let test = tests
+                .entry(key)
+                .or_insert_with_key(|(package, features, name)| FlakyTest {
+                    package: package.clone(),
+                    features: features.clone(),
+                    name: name.clone(),
+                    passed: 0,
+                    failed: 0,
+                });
+            test.passed += passed;
+            test.failed += failed;
+        }
+    }
+
+    tests.retain(|_, t| t.passed > 0 && t.failed > 0);
+    FlakyTests { tests }
+}
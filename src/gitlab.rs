@@ -0,0 +1,140 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test failures as a GitLab Code Quality report. (GitLab's test
+//! reports are JUnit XML, see [`crate::junit`].)
+
+use crate::annotations::{distinct_failures, AnnotatedFailure, Annotation, AnnotationLevel};
+use crate::TestRun;
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// Writes a Code Quality issue for every annotation of a failure in
+/// `test_runs`, located where it was annotated.
+pub fn write<W: Write>(
+    w: W,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+) -> miette::Result<()> {
+    let mut issues = Vec::new();
+    for AnnotatedFailure {
+        test_run,
+        result,
+        failure,
+        kind,
+        annotation,
+        ..
+    } in distinct_failures(test_runs, annotations)
+    {
+        let Some(annotation) = annotation else {
+            continue;
+        };
+        let check_name = kind.id();
+
+        let mut description = format!("{} failed", result.name);
+        if annotation.feature_sets.iter().any(|f| !f.is_empty()) {
+            let feature_sets: Vec<_> = annotation
+                .feature_sets
+                .iter()
+                .map(|f| format!("[{}]", f.join(", ")))
+                .collect();
+            description += &format!(" with features {}", feature_sets.join(", "));
+        }
+        if let Some(cause) = failure.panic_text.lines().find(|l| !l.trim().is_empty()) {
+            description += &format!(": {}", cause.trim());
+        }
+
+        issues.push(Issue {
+            description,
+            check_name,
+            fingerprint: fingerprint(
+                &test_run.package.name,
+                &annotation.feature_sets,
+                &result.name,
+                check_name,
+            ),
+            severity: match annotation.level {
+                AnnotationLevel::Failure => "critical",
+                AnnotationLevel::Warning => "minor",
+                AnnotationLevel::Notice => "info",
+            },
+            location: Location {
+                path: annotation.target.path.clone(),
+                lines: Lines {
+                    begin: annotation.target.line,
+                },
+            },
+        });
+    }
+
+    serde_json::to_writer_pretty(w, &issues).into_diagnostic()
+}
+
+/// Identifies the failure across runs, so GitLab can tell new failures apart
+/// from ones that were already there. It deliberately leaves out the line,
+/// which changes whenever code above the failure does, and the severity, which
+/// changes when the test turns out to be flaky.
+fn fingerprint(
+    package: &str,
+    feature_sets: &[Vec<String>],
+    test_name: &str,
+    check_name: &str,
+) -> String {
+    let mut feature_sets: Vec<_> = feature_sets
+        .iter()
+        .map(|features| {
+            let mut features = features.clone();
+            features.sort();
+            features.join(",")
+        })
+        .collect();
+    feature_sets.sort();
+
+    let mut hasher = Sha256::new();
+    for part in [package, &feature_sets.join(";"), test_name, check_name] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Issue {
+    description: String,
+    check_name: &'static str,
+    fingerprint: String,
+    severity: &'static str,
+    location: Location,
+}
+
+#[derive(Serialize)]
+struct Location {
+    path: String,
+    lines: Lines,
+}
+
+#[derive(Serialize)]
+struct Lines {
+    begin: u64,
+}
@@ -0,0 +1,220 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test runs as JUnit XML.
+
+use crate::{strip_ansi, TestData, TestResult, TestResultValue, TestRun};
+use std::io::Write;
+use std::time::Duration;
+
+/// Writes `test_runs` as JUnit XML, with a `<testsuite>` for each test binary.
+///
+/// The test harness doesn't report how long each test took, so test cases have
+/// a time of zero, and suites only have a time when it can be attributed to
+/// them, i.e. when the test run consisted of a single test binary.
+pub fn write<W: Write>(mut w: W, name: &str, test_runs: &[TestRun]) -> std::io::Result<()> {
+    let mut suites = Vec::new();
+    for test_run in test_runs {
+        suites.extend(suites_of(test_run, &test_run.test_run, false));
+        suites.extend(suites_of(test_run, &test_run.doc_test_run, true));
+    }
+
+    let count = |f: fn(&TestResult) -> bool| -> usize {
+        suites
+            .iter()
+            .map(|s| s.test_results.iter().filter(|t| f(t)).count())
+            .sum()
+    };
+    let tests = count(|_| true);
+    let failures = count(|t| matches!(t.result, TestResultValue::Failed(_)));
+    let skipped = count(|t| matches!(t.result, TestResultValue::Ignored { .. }));
+    let time: Duration = test_runs.iter().map(|r| r.total_time()).sum();
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<testsuites name="{}" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#,
+        escape_attribute(name),
+        tests,
+        failures,
+        skipped,
+        time.as_secs_f64()
+    )?;
+    for suite in &suites {
+        suite.write(&mut w)?;
+    }
+    writeln!(w, "</testsuites>")
+}
+
+struct TestSuite<'a> {
+    name: String,
+    package: &'a str,
+    features: &'a [String],
+    is_doctest: bool,
+    time: Option<Duration>,
+    test_results: Vec<&'a TestResult>,
+}
+
+/// Splits the results of `test_data` by the test binary they ran in.
+fn suites_of<'a>(
+    test_run: &'a TestRun,
+    test_data: &'a TestData,
+    is_doctest: bool,
+) -> Vec<TestSuite<'a>> {
+    let package = test_run.package.name.as_str();
+    let mut suites: Vec<_> = test_data
+        .results_by_target()
+        .into_iter()
+        .map(|(target, test_results)| TestSuite {
+            name: match (target, is_doctest) {
+                (Some(target), false) => format!("{}::{}", package, target),
+                (Some(target), true) => format!("{}::{} (doctests)", package, target),
+                (None, false) => package.to_owned(),
+                (None, true) => format!("{} (doctests)", package),
+            },
+            package,
+            features: &test_run.features,
+            is_doctest,
+            time: None,
+            test_results,
+        })
+        .collect();
+    if let [suite] = &mut suites[..] {
+        suite.time = Some(test_data.test_summary.total_time());
+    }
+
+    suites
+}
+
+impl TestSuite<'_> {
+    fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let failures = self
+            .test_results
+            .iter()
+            .filter(|t| matches!(t.result, TestResultValue::Failed(_)))
+            .count();
+        let skipped = self
+            .test_results
+            .iter()
+            .filter(|t| matches!(t.result, TestResultValue::Ignored { .. }))
+            .count();
+        write!(
+            w,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="0" skipped="{}""#,
+            escape_attribute(&self.name),
+            self.test_results.len(),
+            failures,
+            skipped
+        )?;
+        if let Some(time) = self.time {
+            write!(w, r#" time="{:.3}""#, time.as_secs_f64())?;
+        }
+        writeln!(w, ">")?;
+        if !self.features.is_empty() {
+            writeln!(w, "    <properties>")?;
+            writeln!(
+                w,
+                r#"      <property name="features" value="{}"/>"#,
+                escape_attribute(&self.features.join(","))
+            )?;
+            writeln!(w, "    </properties>")?;
+        }
+
+        for test_result in &self.test_results {
+            // Doctest names start with the path of a file rather than a module.
+            let (classname, name) = match test_result.name.rsplit_once("::") {
+                Some((module, name)) if !self.is_doctest => {
+                    (format!("{}::{}", self.package, module), name)
+                }
+                _ => (self.package.to_owned(), test_result.name.as_str()),
+            };
+            write!(
+                w,
+                r#"    <testcase classname="{}" name="{}" time="0.000""#,
+                escape_attribute(&classname),
+                escape_attribute(name)
+            )?;
+            match &test_result.result {
+                TestResultValue::Ok => writeln!(w, "/>")?,
+                TestResultValue::Ignored { reason } => {
+                    writeln!(w, ">")?;
+                    match reason {
+                        Some(reason) => writeln!(
+                            w,
+                            r#"      <skipped message="{}"/>"#,
+                            escape_attribute(reason)
+                        )?,
+                        None => writeln!(w, "      <skipped/>")?,
+                    }
+                    writeln!(w, "    </testcase>")?;
+                }
+                TestResultValue::Failed(failure) => {
+                    writeln!(w, ">")?;
+                    let details = if failure.stacktrace.is_empty() {
+                        &failure.panic_text
+                    } else {
+                        &failure.stacktrace
+                    };
+                    writeln!(
+                        w,
+                        r#"      <failure message="{}" type="panic">{}</failure>"#,
+                        escape_attribute(&failure.panic_text),
+                        escape(details)
+                    )?;
+                    if !failure.output.is_empty() {
+                        writeln!(
+                            w,
+                            "      <system-out>{}</system-out>",
+                            escape(&strip_ansi(&failure.output))
+                        )?;
+                    }
+                    writeln!(w, "    </testcase>")?;
+                }
+            }
+        }
+
+        writeln!(w, "  </testsuite>")
+    }
+}
+
+/// Escapes `text` for use as element content, dropping the control characters
+/// XML 1.0 doesn't allow, like the ones in ANSI escape sequences.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\n' | '\r' | '\t' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes `text` for use as an attribute value, where line breaks would
+/// otherwise be normalized to spaces.
+pub(crate) fn escape_attribute(text: &str) -> String {
+    escape(text)
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+        .replace('\t', "&#9;")
+}
@@ -16,57 +16,423 @@
 //
 // Licensed under the MIT License. See LICENSE-MIT for details.
 
-use cargo_metadata::{Artifact, Message, MessageIter, Metadata, Package};
-use miette::{Diagnostic, IntoDiagnostic};
+use cargo_metadata::{Artifact, Message, Metadata, Package, Target};
+use miette::{Context, Diagnostic, IntoDiagnostic};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::io::{BufRead, Read};
-use std::iter::Peekable;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
 
+pub mod annotations;
+pub mod baseline;
+pub mod config;
+pub mod dump;
+pub mod flaky;
+pub mod gitlab;
+pub mod junit;
+pub mod paths;
+pub mod reviewdog;
+pub mod run;
+pub mod sarif;
+pub mod source;
+pub mod summary;
+pub mod tap;
+pub mod template;
+pub mod workflow;
+
+/// Parses the output of `cargo test --message-format json`. The output of
+/// several invocations may be concatenated, e.g. to test different features.
+///
+/// Each test binary's output is matched up with the binary by the `Running`
+/// and `Doc-tests` headers cargo prints to stderr, if stderr was captured
+/// along with stdout, and otherwise by the order cargo runs the binaries in.
 pub fn parse<R: Read>(r: R, metadata: Metadata) -> miette::Result<Vec<TestRun>> {
     let workspace_packages = metadata.workspace_packages();
     let reader = std::io::BufReader::new(r);
 
-    let mut current_artifact = None;
     let mut test_runs = Vec::new();
-    let mut message_iter = Message::parse_stream(reader).peekable();
-    while let Some(message) = message_iter.next() {
+    let mut invocation = Invocation::default();
+    for message in Message::parse_stream(reader) {
         match message.into_diagnostic()? {
-            Message::CompilerArtifact(
-                artifact @ Artifact {
-                    executable: Some(_),
-                    ..
-                },
-            ) => {
-                current_artifact = Some(artifact);
+            Message::CompilerArtifact(artifact) => {
+                // Artifacts following test output were built by the next
+                // invocation.
+                if invocation.ran_tests {
+                    test_runs.extend(std::mem::take(&mut invocation).parse(&workspace_packages)?);
+                }
+                invocation.artifacts.push(artifact);
             }
-            Message::TextLine(_) => {
-                let artifact = current_artifact
-                    .as_ref()
-                    .expect("No current artifact. Is the input data malformed?");
-                let package = workspace_packages
-                    .iter()
-                    .copied()
-                    .find(|&w| w.id == artifact.package_id)
-                    .ok_or_else(|| {
+            Message::TextLine(text) => invocation.push_line(text),
+            _ => {} // Irrelevant messages
+        }
+    }
+    test_runs.extend(invocation.parse(&workspace_packages)?);
+
+    Ok(test_runs)
+}
+
+/// Parses the human-readable output of `cargo test`, i.e. what it prints to the
+/// terminal when run without `--message-format json`. Both stdout and stderr
+/// must be captured, since that's where cargo announces each test binary.
+pub fn parse_text<R: Read>(r: R, metadata: Metadata) -> miette::Result<Vec<TestRun>> {
+    let reader = std::io::BufReader::new(r);
+    let mut invocation = Invocation::default();
+    for line in reader.lines() {
+        invocation.push_line(line.into_diagnostic()?);
+    }
+    invocation.parse(&metadata.workspace_packages())
+}
+
+/// Parses the files matching the glob `pattern` with `parse`, in alphabetical
+/// order, and records which file each test run came from.
+pub fn parse_files<F>(pattern: &str, mut parse: F) -> miette::Result<Vec<TestRun>>
+where
+    F: FnMut(std::fs::File) -> miette::Result<Vec<TestRun>>,
+{
+    let mut test_runs = Vec::new();
+    for path in expand(pattern)? {
+        let file = std::fs::File::open(&path)
+            .into_diagnostic()
+            .with_context(|| path.display().to_string())?;
+        let mut file_test_runs = parse(file).with_context(|| path.display().to_string())?;
+        for test_run in &mut file_test_runs {
+            test_run.source_file = Some(path.display().to_string());
+        }
+        test_runs.extend(file_test_runs);
+    }
+    Ok(test_runs)
+}
+
+/// The files matching `pattern`, in alphabetical order. A path without glob
+/// metacharacters is taken as is.
+pub fn expand(pattern: &str) -> miette::Result<Vec<PathBuf>> {
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+
+    let paths = glob::glob(pattern)
+        .into_diagnostic()
+        .with_context(|| pattern.to_owned())?
+        .collect::<Result<Vec<_>, _>>()
+        .into_diagnostic()
+        .with_context(|| pattern.to_owned())?;
+    if paths.is_empty() {
+        miette::bail!("no test output files match `{}`", pattern);
+    }
+    Ok(paths)
+}
+
+/// The output of a single `cargo test`: the artifacts it built, if known, and
+/// the text it printed.
+#[derive(Debug, Default)]
+struct Invocation {
+    artifacts: Vec<Artifact>,
+    lines: Vec<String>,
+    ran_tests: bool,
+}
+
+impl Invocation {
+    fn push_line(&mut self, text: String) {
+        self.ran_tests |= RUNNING_REGEX.with(|r| r.is_match(&text));
+        self.lines.push(text);
+    }
+
+    /// Parses the output of each test binary in turn. Unit tests, integration
+    /// tests and doctests of the same package all run as separate binaries,
+    /// but they make up a single test run.
+    fn parse(self, workspace_packages: &[&Package]) -> miette::Result<Vec<TestRun>> {
+        let Invocation {
+            artifacts, lines, ..
+        } = self;
+        let mut expected = run_order(&artifacts, workspace_packages);
+        let mut lines = TextLines::new(lines);
+
+        let mut test_runs: Vec<(BinaryTarget, TestDataParseResult, TestDataParseResult)> =
+            Vec::new();
+        while let Some(position) = lines.find_position(lines.position, |l| {
+            TestBinary::from_header(l).is_some() || RUNNING_REGEX.with(|r| r.is_match(l))
+        }) {
+            lines.position = position;
+            let text = lines.next().expect("found the line");
+            let binary = match TestBinary::from_header(&text) {
+                Some(header) => {
+                    let binary =
+                        header
+                            .resolve(&artifacts, workspace_packages)
+                            .ok_or_else(|| {
+                                miette::miette!(
+                                    "could not find package for test binary '{}' in workspace",
+                                    text.trim()
+                                )
+                            })?;
+                    expected.retain(|b| !b.is_same(&binary));
+                    binary
+                }
+                None => {
+                    lines.unread();
+                    expected.pop_front().ok_or_else(|| {
                         miette::miette!(
-                            "could not find package '{}' from test in workspace",
-                            artifact.package_id
+                            help = "was the output produced by `cargo test --message-format json`?",
+                            "could not tell which test binary some of the test output came from"
                         )
-                    })?;
-                let features = artifact.features.clone();
-                let test_run_parser = TestRunParser::new(package.clone(), features);
-                test_runs.push(test_run_parser.parse(&mut message_iter)?);
-                while let Some(Ok(Message::TextLine(_))) = message_iter.peek() {
-                    let _ = message_iter.next();
+                    })?
+                }
+            };
+
+            let mut test_run_parser = TestRunParser::new();
+            let target = Some(binary.target.name.as_str());
+            let (test_run, doc_test_run) = if binary.is_doctest {
+                (
+                    TestDataParseResult::default(),
+                    test_run_parser
+                        .parse_doc_test_data(&mut lines)?
+                        .with_target(target),
+                )
+            } else {
+                (
+                    test_run_parser
+                        .parse_test_data(&mut lines)?
+                        .with_target(target),
+                    TestDataParseResult::default(),
+                )
+            };
+
+            let same_run = test_runs
+                .iter_mut()
+                .find(|(b, ..)| b.package.id == binary.package.id && b.features == binary.features);
+            match same_run {
+                Some((_, t, d)) => {
+                    *t = std::mem::take(t).merge(test_run);
+                    *d = std::mem::take(d).merge(doc_test_run);
                 }
+                None => test_runs.push((binary, test_run, doc_test_run)),
             }
-            _ => {} // Irrelevant messages
         }
+
+        Ok(test_runs
+            .into_iter()
+            .map(|(binary, test_run, doc_test_run)| TestRun {
+                package: binary.package.clone(),
+                features: binary.features,
+                test_run: test_run.into(),
+                doc_test_run: doc_test_run.into(),
+                source_file: None,
+            })
+            .collect())
     }
+}
 
-    Ok(test_runs)
+/// A test binary, along with the package and target it was built from.
+#[derive(Clone, Debug)]
+struct BinaryTarget<'a> {
+    package: &'a Package,
+    target: &'a Target,
+    features: Vec<String>,
+    is_doctest: bool,
+}
+
+impl<'a> BinaryTarget<'a> {
+    fn from_artifact(
+        artifact: &Artifact,
+        workspace_packages: &[&'a Package],
+        is_doctest: bool,
+    ) -> Option<Self> {
+        let package = workspace_packages
+            .iter()
+            .copied()
+            .find(|p| p.id == artifact.package_id)?;
+        let target = package
+            .targets
+            .iter()
+            .find(|t| t.name == artifact.target.name && t.kind == artifact.target.kind)?;
+        Some(Self {
+            package,
+            target,
+            features: artifact.features.clone(),
+            is_doctest,
+        })
+    }
+
+    fn is_same(&self, other: &Self) -> bool {
+        self.package.id == other.package.id
+            && self.target.name == other.target.name
+            && self.is_doctest == other.is_doctest
+    }
+}
+
+/// The test binaries built by `artifacts`, in the order `cargo test` runs
+/// them: the unit and integration tests, ordered by package, kind of target
+/// and name, followed by the doctests of each package.
+fn run_order<'a>(
+    artifacts: &[Artifact],
+    workspace_packages: &[&'a Package],
+) -> VecDeque<BinaryTarget<'a>> {
+    let kind_order = |t: &Target| {
+        if t.is_bin() {
+            1
+        } else if t.is_test() {
+            2
+        } else if t.is_bench() {
+            3
+        } else if t.is_example() {
+            4
+        } else {
+            0
+        }
+    };
+    let package_order = |b: &BinaryTarget<'a>| (b.package.name.as_str(), &b.package.version);
+
+    let mut tests: Vec<_> = artifacts
+        .iter()
+        .filter(|a| a.profile.test && a.executable.is_some())
+        .filter_map(|a| BinaryTarget::from_artifact(a, workspace_packages, false))
+        .collect();
+    tests.sort_by(|a, b| {
+        (package_order(a), kind_order(a.target), &a.target.name).cmp(&(
+            package_order(b),
+            kind_order(b.target),
+            &b.target.name,
+        ))
+    });
+    // Doctests are run against the library as it's built for other crates.
+    let mut doc_tests: Vec<_> = artifacts
+        .iter()
+        .filter(|a| !a.profile.test && a.target.doctest)
+        .filter_map(|a| BinaryTarget::from_artifact(a, workspace_packages, true))
+        .collect();
+    doc_tests.sort_by(|a, b| package_order(a).cmp(&package_order(b)));
+    doc_tests.dedup_by(|a, b| a.is_same(b));
+
+    tests.into_iter().chain(doc_tests).collect()
+}
+
+/// A test binary, as announced by cargo before running it.
+#[derive(Clone, Debug)]
+enum TestBinary {
+    Tests {
+        src_path: Option<String>,
+        executable: String,
+    },
+    DocTests {
+        crate_name: String,
+    },
+}
+
+impl TestBinary {
+    fn from_header(text: &str) -> Option<Self> {
+        if let Some(c) = RUNNING_BINARY_REGEX.with(|r| r.captures(text)) {
+            let executable = c
+                .name("executable")
+                .or_else(|| c.name("bare_executable"))
+                .expect("<executable>")
+                .as_str()
+                .to_owned();
+            let src_path = c.name("src_path").map(|m| m.as_str().to_owned());
+            Some(Self::Tests {
+                src_path,
+                executable,
+            })
+        } else {
+            DOC_TESTS_REGEX
+                .with(|r| r.captures(text))
+                .map(|c| Self::DocTests {
+                    crate_name: c
+                        .name("crate_name")
+                        .expect("<crate_name>")
+                        .as_str()
+                        .to_owned(),
+                })
+        }
+    }
+
+    /// Finds the package, target and features the test binary was built
+    /// from, preferring the artifact with the same executable.
+    fn resolve<'a>(
+        &self,
+        artifacts: &[Artifact],
+        workspace_packages: &[&'a Package],
+    ) -> Option<BinaryTarget<'a>> {
+        let file_name = |path: &str| {
+            path.rsplit(['/', '\\'])
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        };
+        let is_doctest = matches!(self, Self::DocTests { .. });
+        if let Self::Tests { executable, .. } = self {
+            let artifact = artifacts.iter().find(|a| {
+                a.executable
+                    .as_ref()
+                    .is_some_and(|e| file_name(e.as_str()) == file_name(executable))
+            });
+            if let Some(binary) =
+                artifact.and_then(|a| BinaryTarget::from_artifact(a, workspace_packages, false))
+            {
+                return Some(binary);
+            }
+        }
+
+        let (package, target) = self.find_target(workspace_packages)?;
+        let features = artifacts
+            .iter()
+            .find(|a| a.package_id == package.id && a.target.name == target.name)
+            .map(|a| a.features.clone())
+            .unwrap_or_default();
+        Some(BinaryTarget {
+            package,
+            target,
+            features,
+            is_doctest,
+        })
+    }
+
+    /// Finds the package and target the test binary was built from.
+    fn find_target<'a>(
+        &self,
+        workspace_packages: &[&'a Package],
+    ) -> Option<(&'a Package, &'a Target)> {
+        let normalize_name = |name: &str| name.replace('-', "_");
+        let normalize_path = |path: &str| path.replace('\\', "/");
+        workspace_packages.iter().copied().find_map(|p| {
+            let target = p.targets.iter().find(|t| match self {
+                Self::Tests {
+                    src_path,
+                    executable,
+                } => {
+                    // The executable is named after the target, followed by a
+                    // hash.
+                    let executable = normalize_path(executable);
+                    let file_name = executable.rsplit('/').next().unwrap_or_default();
+                    let file_stem = file_name.strip_suffix(".exe").unwrap_or(file_name);
+                    let name_matches = file_stem
+                        .rsplit_once('-')
+                        .is_some_and(|(name, _)| name == normalize_name(&t.name));
+
+                    // Cargo prints the source path relative to the package
+                    // root, so it only tells packages apart when their targets
+                    // have the same name. Older versions of cargo don't print
+                    // it at all.
+                    let src_path_matches = src_path.as_deref().is_none_or(|src_path| {
+                        let src_path = normalize_path(src_path);
+                        let target_src_path = normalize_path(t.src_path.as_str());
+                        target_src_path == src_path
+                            || target_src_path.ends_with(&format!("/{}", src_path))
+                    });
+
+                    name_matches && src_path_matches
+                }
+                Self::DocTests { crate_name } => {
+                    t.doctest && normalize_name(&t.name) == normalize_name(crate_name)
+                }
+            })?;
+            Some((p, target))
+        })
+    }
 }
 
 #[macro_export]
@@ -91,36 +457,66 @@ macro_rules! parse_capture {
     };
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestRun {
     pub package: Package,
     pub features: Vec<String>,
     pub test_run: TestData,
     pub doc_test_run: TestData,
+    /// The file the test output was read from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
 }
-impl From<TestRunParser> for TestRun {
-    fn from(parser: TestRunParser) -> Self {
-        let TestRunParser {
-            package,
-            features,
-            test_run,
-            doc_test_run,
-            ..
-        } = parser;
-        Self {
-            package,
-            features,
-            test_run: test_run.map(From::from).unwrap(),
-            doc_test_run: doc_test_run.map(From::from).unwrap(),
-        }
+impl TestRun {
+    /// The combined time spent running tests and doctests, including the time
+    /// it took to compile merged doctests.
+    pub fn total_time(&self) -> Duration {
+        self.test_run.test_summary.total_time() + self.doc_test_run.test_summary.total_time()
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestData {
     pub test_count: usize,
     pub test_results: Vec<TestResult>,
     pub test_summary: TestSummary,
+    /// How long the test binary of each target ran, in the order they ran.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub target_times: Vec<TargetTime>,
+}
+impl TestData {
+    /// How long the test binary of `target` ran, if known. Merged and
+    /// standalone doctests run in binaries of their own, which add up.
+    pub fn target_time(&self, target: Option<&str>) -> Option<Duration> {
+        self.target_times
+            .iter()
+            .filter(|t| t.target.as_deref() == target)
+            .map(|t| t.time)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Like [`TestData::target_time`], but test results from before binaries
+    /// were timed are attributed the whole run time when there was a single
+    /// binary.
+    pub fn binary_time(&self, target: Option<&str>) -> Option<Duration> {
+        self.target_time(target).or_else(|| {
+            (self.results_by_target().len() <= 1).then(|| self.test_summary.total_time())
+        })
+    }
+
+    /// Groups the results by the target whose test binary ran them, in the
+    /// order the binaries ran.
+    pub fn results_by_target(&self) -> Vec<(Option<&str>, Vec<&TestResult>)> {
+        let mut targets: Vec<(Option<&str>, Vec<&TestResult>)> = Vec::new();
+        for test_result in &self.test_results {
+            let target = test_result.target.as_deref();
+            match targets.iter_mut().find(|(t, _)| *t == target) {
+                Some((_, test_results)) => test_results.push(test_result),
+                None => targets.push((target, vec![test_result])),
+            }
+        }
+        targets
+    }
 }
 impl From<TestDataParseResult> for TestData {
     fn from(r: TestDataParseResult) -> Self {
@@ -128,330 +524,614 @@ impl From<TestDataParseResult> for TestData {
             test_count,
             test_results,
             test_summary,
+            target_times,
         } = r;
         Self {
             test_count,
             test_results: test_results.into_iter().map(From::from).collect(),
             test_summary,
+            target_times,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+/// How long the test binary of a target ran.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TargetTime {
+    /// The name of the target, if known.
+    pub target: Option<String>,
+    pub time: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestResult {
     pub name: String,
     pub result: TestResultValue,
+    /// The name of the target whose test binary ran the test, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
 }
 impl From<TestResultParseResult> for TestResult {
     fn from(t: TestResultParseResult) -> Self {
         let TestResultParseResult {
             name,
             kind,
+            ignore_reason,
             failure_info,
+            target,
+            ..
         } = t;
         let result = match kind {
             TestResultKind::Ok => TestResultValue::Ok,
             TestResultKind::Failed => TestResultValue::Failed(failure_info.unwrap()),
+            TestResultKind::Ignored => TestResultValue::Ignored {
+                reason: ignore_reason,
+            },
         };
-        Self { name, result }
+        Self {
+            name,
+            result,
+            target,
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
 pub enum TestResultValue {
     Ok,
     Failed(TestFailureInfo),
+    Ignored { reason: Option<String> },
 }
 
 impl TestResultValue {
     pub fn unwrap_failure(self) -> TestFailureInfo {
         match self {
             Self::Failed(failure) => failure,
-            Self::Ok => panic!("called `TestResultValue::unwrap_failure()` on an `Ok` value"),
+            _ => panic!("called `TestResultValue::unwrap_failure()` on a non-`Failed` value"),
         }
     }
     pub fn unwrap_failure_ref(&self) -> &TestFailureInfo {
         match self {
             Self::Failed(failure) => failure,
-            Self::Ok => panic!("called `TestResultValue::unwrap_failure()` on an `Ok` value"),
+            _ => panic!("called `TestResultValue::unwrap_failure()` on a non-`Failed` value"),
         }
     }
 }
 
 #[derive(Debug)]
 struct TestRunParser {
-    package: Package,
-    features: Vec<String>,
-    phase: TestRunParserPhase,
     state: TestRunParserState,
     test_count: usize,
     test_results: Vec<TestResultParseResult>,
-    test_run: Option<TestDataParseResult>,
-    doc_test_run: Option<TestDataParseResult>,
+    /// The test that was announced with `test <name> ... ` most recently, but
+    /// whose result hasn't been printed yet. This only happens when tests run
+    /// on a single thread, or when they print output without a newline.
+    pending_test: Option<PendingTest>,
+    /// The output of tests that panicked while the result listing was being
+    /// printed, which happens when output isn't captured (`--nocapture`).
+    panic_outputs: Vec<(String, Vec<String>)>,
+    capturing_panic_output: bool,
+    /// Whether the last line of the listing was printed by a test, so a
+    /// result on the next line may have been too.
+    after_test_output: bool,
+    failure_outputs: Vec<(String, Vec<String>)>,
+    failures_listing: Vec<String>,
+}
+
+#[derive(Debug)]
+struct PendingTest {
+    name: String,
+    announced: bool,
+    /// A result found at the end of a line of output printed by the test
+    /// itself, e.g. `partial lineok`. A later line consisting solely of a
+    /// result takes precedence.
+    tentative_result: Option<(TestResultKind, Option<String>)>,
 }
 
 impl TestRunParser {
-    fn new(package: Package, features: Vec<String>) -> Self {
+    fn new() -> Self {
         Self {
-            package,
-            features,
             state: TestRunParserState::Initial,
-            phase: TestRunParserPhase::Tests,
             test_count: 0,
             test_results: Vec::new(),
-            test_run: None,
-            doc_test_run: None,
-        }
-    }
-
-    pub fn parse<R: BufRead>(
-        mut self,
-        message_iter: &mut Peekable<MessageIter<R>>,
-    ) -> miette::Result<TestRun> {
-        while self.phase != TestRunParserPhase::Done {
-            while self.state != TestRunParserState::Done {
-                let message = message_iter
-                    .next()
-                    .expect("we're in the middle of parsing")
-                    .into_diagnostic()?;
-                match message {
-                    Message::TextLine(text) => match self.state {
-                        TestRunParserState::Initial => {
-                            RUNNING_REGEX.with(|r| -> miette::Result<()> {
-                                if let Some(c) = r.captures(&text) {
-                                    parse_capture!(let test_count: usize = c => "count");
-                                    self.test_count = test_count;
-                                    if test_count > 0 {
-                                        self.state = TestRunParserState::Tests;
-                                    } else {
-                                        self.state = TestRunParserState::Results;
-                                    }
-                                }
-
-                                Ok(())
-                            })?;
-                        }
-                        TestRunParserState::Tests => {
-                            TEST_REGEX.with(|r| -> miette::Result<()> {
-                                if let Some(c) = r.captures(&text) {
-                                    parse_capture!(let name: String = c);
-                                    parse_capture!(let result: TestResultKind = c);
-                                    self.test_results
-                                        .push(TestResultParseResult::new(name, result));
-                                } else {
-                                    #[allow(clippy::collapsible_else_if)]
-                                    if self
-                                        .test_results
-                                        .iter()
-                                        .any(|r| r.kind == TestResultKind::Failed)
-                                    {
-                                        self.state = TestRunParserState::FailuresOutput;
-                                    } else {
-                                        self.state = TestRunParserState::Results;
-                                    }
-                                }
-
-                                Ok(())
-                            })?;
-                        }
-                        TestRunParserState::FailuresOutput => {
-                            let mut more_failures = true;
-                            let mut text = text;
-                            let mut failure_parsing_state = TestRunFailureParserState::Initial;
-                            while more_failures {
-                                let mut text_inner = text;
-                                let mut name = None;
-                                let mut panic_text = None;
-                                let mut location = None;
-                                let mut stacktrace = None;
-                                while failure_parsing_state != TestRunFailureParserState::Done {
-                                    match failure_parsing_state {
-                                        TestRunFailureParserState::Initial => {
-                                            if text_inner.trim() == "failures:" {
-                                                failure_parsing_state =
-                                                    TestRunFailureParserState::Header;
-                                            }
-                                        }
-                                        TestRunFailureParserState::Header => {
-                                            FAILURE_HEADER_REGEX.with(
-                                                |r| -> miette::Result<()> {
-                                                    if let Some(c) = r.captures(&text_inner) {
-                                                        parse_capture!(let n: String = c => "name");
-
-                                                        name = Some(n);
-                                                        failure_parsing_state =
-                                                            TestRunFailureParserState::Panic;
-                                                    }
-
-                                                    Ok(())
-                                                },
-                                            )?;
-                                        }
-                                        TestRunFailureParserState::Panic => {
-                                            while let Some(Ok(Message::TextLine(t))) =
-                                                message_iter.peek()
-                                            {
-                                                if t.trim() != "stack backtrace:" {
-                                                    text_inner.push('\n');
-                                                    text_inner.push_str(t);
-                                                    let _ = message_iter.next();
-                                                } else {
-                                                    let rpos = text_inner
-                                                        .rfind(',')
-                                                        .expect("regular panic format");
-                                                    let (pt, l) = text_inner.split_at(rpos);
-                                                    let l = l
-                                                        .strip_prefix(", ")
-                                                        .expect("regular panic format")
-                                                        .trim();
-                                                    panic_text = Some(pt.to_owned());
-                                                    location = Some(l.to_owned());
-
-                                                    failure_parsing_state =
-                                                        TestRunFailureParserState::Stacktrace;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                        TestRunFailureParserState::Stacktrace => {
-                                            while let Some(Ok(Message::TextLine(t))) =
-                                                message_iter.peek()
-                                            {
-                                                if !t.trim().is_empty() {
-                                                    text_inner.push('\n');
-                                                    text_inner.push_str(t);
-                                                    let _ = message_iter.next();
-                                                } else {
-                                                    stacktrace = Some(text_inner.clone());
-
-                                                    failure_parsing_state =
-                                                        TestRunFailureParserState::Done;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                        TestRunFailureParserState::Done => unreachable!(),
-                                    }
-                                    match message_iter
-                                        .next()
-                                        .expect("we're not done")
-                                        .expect("we're not done")
-                                    {
-                                        Message::TextLine(t) => {
-                                            text_inner = t;
-                                        }
-                                        m => {
-                                            miette::bail!(
-                                                "Encountered unexpected message: {:?} while parser was in state {:?}",
-                                                m,
-                                                self
-                                            )
-                                        }
-                                    }
-                                }
-                                let failure_info = TestFailureInfo::new(
-                                    panic_text.unwrap(),
-                                    location.as_ref().unwrap().parse().into_diagnostic()?,
-                                    stacktrace.unwrap(),
-                                );
-                                let result = self
-                                    .test_results
-                                    .iter_mut()
-                                    .find(|r| r.name == name.as_deref().unwrap())
-                                    .unwrap();
-                                result.failure_info = Some(failure_info);
-
-                                FAILURE_HEADER_REGEX.with(|r| {
-                                    while let Some(Ok(Message::TextLine(t))) = message_iter.peek() {
-                                        if r.is_match(t) {
-                                            failure_parsing_state =
-                                                TestRunFailureParserState::Header;
-                                            break;
-                                        } else if t.trim() == "failures:" {
-                                            more_failures = false;
-                                            break;
-                                        } else {
-                                            let _ = message_iter.next();
-                                        }
-                                    }
-                                });
-                                text = String::new();
-                            }
+            pending_test: None,
+            panic_outputs: Vec::new(),
+            capturing_panic_output: false,
+            after_test_output: false,
+            failure_outputs: Vec::new(),
+            failures_listing: Vec::new(),
+        }
+    }
 
-                            self.state = TestRunParserState::FailuresListing;
-                        }
-                        TestRunParserState::FailuresListing => {
-                            while let Some(Ok(Message::TextLine(t))) = message_iter.peek() {
-                                if !t.trim().is_empty() {
-                                    let _ = message_iter.next();
-                                } else {
-                                    self.state = TestRunParserState::Results;
-                                    break;
-                                }
+    fn parse_doc_test_data(
+        &mut self,
+        lines: &mut TextLines,
+    ) -> miette::Result<TestDataParseResult> {
+        let mut doc_test_run = self.parse_test_data(lines)?;
+        // Since the 2024 edition, rustdoc merges doctests into a single binary
+        // where it can. Doctests that can't be merged run afterwards as a
+        // separate block, and both are followed by a line reporting how long
+        // the merged doctests took to compile. A block that isn't followed by
+        // that line belongs to the next test binary.
+        let next = lines.next_non_blank_position(lines.position);
+        if RUNNING_REGEX.with(|r| lines.get(next).is_some_and(|l| r.is_match(l))) {
+            let result = RESULT_REGEX.with(|r| lines.find_position(next, |l| r.is_match(l)));
+            if let Some(result) = result {
+                let after_result = lines.next_non_blank_position(result + 1);
+                if ALL_DOCTESTS_REGEX
+                    .with(|r| lines.get(after_result).is_some_and(|l| r.is_match(l)))
+                {
+                    let standalone_doc_test_run = self.parse_test_data(lines)?;
+                    doc_test_run = doc_test_run.merge(standalone_doc_test_run);
+                }
+            }
+        }
+
+        let next = lines.next_non_blank_position(lines.position);
+        let all_doctests = lines
+            .get(next)
+            .and_then(|l| ALL_DOCTESTS_REGEX.with(|r| r.captures(l)));
+        if let Some(c) = all_doctests {
+            parse_capture!(let merged_compilation_time: f64 = c);
+            doc_test_run.test_summary.merged_compilation_time =
+                Some(Duration::from_secs_f64(merged_compilation_time));
+            lines.position = next + 1;
+        }
+
+        Ok(doc_test_run)
+    }
+
+    fn parse_test_data(&mut self, lines: &mut TextLines) -> miette::Result<TestDataParseResult> {
+        self.state = TestRunParserState::Initial;
+        self.test_count = 0;
+
+        let mut test_data = None;
+        while self.state != TestRunParserState::Done {
+            let Some(text) = lines.next() else {
+                miette::bail!(
+                    "Encountered unexpected end of test output while parser was in state {:?}",
+                    self
+                );
+            };
+            match self.state {
+                TestRunParserState::Initial => {
+                    RUNNING_REGEX.with(|r| -> miette::Result<()> {
+                        if let Some(c) = r.captures(&text) {
+                            parse_capture!(let test_count: usize = c => "count");
+                            self.test_count = test_count;
+                            if test_count > 0 {
+                                self.state = TestRunParserState::Tests;
+                            } else {
+                                self.state = TestRunParserState::Results;
                             }
                         }
-                        TestRunParserState::Results => {
-                            RESULT_REGEX.with(|r| -> miette::Result<()> {
-                                if let Some(c) = r.captures(&text) {
-                                    parse_capture!(let result: TestResultKind = c);
-                                    parse_capture!(let passed: usize = c);
-                                    parse_capture!(let failed: usize = c);
-                                    parse_capture!(let ignored: usize = c);
-                                    parse_capture!(let measured: usize = c);
-                                    parse_capture!(let filtered: usize = c);
-                                    parse_capture!(let time: String = c);
-
-                                    let test_summary = TestSummary::new(
-                                        result, passed, failed, ignored, measured, filtered, time,
-                                    );
-                                    let test_run = TestDataParseResult::new(
-                                        self.test_count,
-                                        std::mem::take(&mut self.test_results),
-                                        test_summary,
-                                    );
-                                    match self.phase {
-                                        TestRunParserPhase::Tests => {
-                                            self.test_run = Some(test_run);
-                                        }
-                                        TestRunParserPhase::DocTests => {
-                                            self.doc_test_run = Some(test_run);
-                                        }
-                                        TestRunParserPhase::Done => unreachable!(),
-                                    }
-                                    self.state = TestRunParserState::Done;
-                                }
-
-                                Ok(())
-                            })?;
+
+                        Ok(())
+                    })?;
+                }
+                TestRunParserState::Tests => {
+                    // Output printed by the tests themselves (`--nocapture`)
+                    // can appear anywhere in the listing, so only the lines
+                    // that follow it can tell us it's over.
+                    if text.trim() == "failures:" {
+                        self.finish_pending_test();
+                        self.parse_failures(lines)?;
+                        self.state = TestRunParserState::FailuresListing;
+                    } else if RESULT_REGEX.with(|r| r.is_match(&text)) {
+                        self.finish_pending_test();
+                        lines.unread();
+                        self.state = TestRunParserState::Results;
+                    } else {
+                        self.parse_test_line(&text, lines.last_raw())?;
+                    }
+                }
+                TestRunParserState::FailuresListing => {
+                    let name = text.trim();
+                    if name.is_empty() {
+                        self.state = TestRunParserState::Results;
+                    } else if name != "failures:" {
+                        self.failures_listing.push(name.to_owned());
+                    }
+                }
+                TestRunParserState::Results => {
+                    RESULT_REGEX.with(|r| -> miette::Result<()> {
+                        if let Some(c) = r.captures(&text) {
+                            parse_capture!(let result: TestResultKind = c);
+                            parse_capture!(let passed: usize = c);
+                            parse_capture!(let failed: usize = c);
+                            parse_capture!(let ignored: usize = c);
+                            parse_capture!(let measured: usize = c);
+                            parse_capture!(let filtered: usize = c);
+                            parse_capture!(let time: f64 = c);
+                            let merged_compilation_time = c
+                                .name("merged_compilation_time")
+                                .map(|m| m.as_str().parse::<f64>())
+                                .transpose()
+                                .into_diagnostic()?
+                                .map(Duration::from_secs_f64);
+
+                            let test_summary = TestSummary::new(
+                                result,
+                                passed,
+                                failed,
+                                ignored,
+                                measured,
+                                filtered,
+                                Duration::from_secs_f64(time),
+                                merged_compilation_time,
+                            );
+                            test_data = Some(TestDataParseResult::new(
+                                self.test_count,
+                                self.take_test_results(&test_summary)?,
+                                test_summary,
+                            ));
+                            self.state = TestRunParserState::Done;
                         }
-                        TestRunParserState::Done => unreachable!(),
-                    },
-                    m => miette::bail!(
-                        "Encountered unexpected message: {:?} while parser was in state {:?}",
-                        m,
-                        self
-                    ),
+
+                        Ok(())
+                    })?;
                 }
+                TestRunParserState::Done => unreachable!(),
             }
-            match self.phase {
-                TestRunParserPhase::Tests => {
-                    self.phase = TestRunParserPhase::DocTests;
-                    self.state = TestRunParserState::Initial;
-                    self.test_count = 0;
+        }
+
+        Ok(test_data.expect("parser is done"))
+    }
+
+    /// Parses a line of the `test <name> ... <result>` listing, which, when
+    /// output isn't captured, may be interleaved with whatever the tests print.
+    /// `raw` is the line as it was printed, which is what's kept as output.
+    fn parse_test_line(&mut self, text: &str, raw: &str) -> miette::Result<()> {
+        let after_test_output = self.after_test_output;
+        if !text.trim().is_empty() {
+            self.after_test_output = true;
+        }
+
+        if let Some(thread) = PANIC_HEADER_REGEX.with(|r| r.captures(text)) {
+            let thread = thread.name("thread").expect("<thread>").as_str().to_owned();
+            self.panic_outputs.push((thread, vec![raw.to_owned()]));
+            self.capturing_panic_output = true;
+            return Ok(());
+        }
+
+        let announcements = TEST_REGEX.with(|r| {
+            r.captures_iter(text)
+                .map(|c| -> miette::Result<_> {
+                    let m = c.get(0).expect("match");
+                    let announced = m.start() == 0 && !after_test_output;
+                    let name = c.name("name").expect("<name>").as_str().to_owned();
+                    let result = c
+                        .name("result")
+                        .map(|m| m.as_str().parse::<TestResultKind>())
+                        .transpose()
+                        .into_diagnostic()?;
+                    let reason = c.name("reason").map(|m| m.as_str().to_owned());
+                    Ok((name, result.map(|r| (r, reason)), announced, m.end()))
+                })
+                .collect::<miette::Result<Vec<_>>>()
+        })?;
+
+        let Some((_, _, _, last_end)) = announcements.last() else {
+            let result = BARE_RESULT_REGEX.with(|r| r.captures(text)).map(|c| {
+                let result = c
+                    .name("result")
+                    .expect("<result>")
+                    .as_str()
+                    .parse::<TestResultKind>();
+                (result, c.name("reason").map(|m| m.as_str().to_owned()))
+            });
+            match (result, &mut self.pending_test) {
+                (Some((result, reason)), Some(_)) => {
+                    let pending_test = self.pending_test.take().expect("pending test");
+                    self.push_test_result(
+                        pending_test.name,
+                        result.into_diagnostic()?,
+                        reason,
+                        pending_test.announced,
+                    );
+                    self.capturing_panic_output = false;
+                    self.after_test_output = false;
+                }
+                // A result without a test to go with it was printed by a test.
+                (Some(_), None) => {}
+                (None, _) => {
+                    if self.capturing_panic_output {
+                        if text.trim().is_empty() {
+                            self.capturing_panic_output = false;
+                        } else {
+                            let (_, output) = self.panic_outputs.last_mut().expect("capturing");
+                            output.push(raw.to_owned());
+                        }
+                    }
+                    self.set_tentative_result(text);
+                }
+            }
+
+            return Ok(());
+        };
+        let last_end = *last_end;
+        if announcements.iter().all(|(_, _, announced, _)| *announced) {
+            self.after_test_output = last_end < text.trim_end().len();
+        }
+
+        self.capturing_panic_output = false;
+        for (name, result, announced, _) in announcements {
+            if let Some(pending_test) = &self.pending_test {
+                if pending_test.tentative_result.is_none() {
+                    // When tests run on a single thread, the next test is only
+                    // announced once the previous one has a result, so this
+                    // was printed by the test that's still running.
+                    continue;
                 }
-                TestRunParserPhase::DocTests => self.phase = TestRunParserPhase::Done,
-                TestRunParserPhase::Done => unreachable!(),
+                self.finish_pending_test();
+            }
+
+            match result {
+                Some((result, reason)) => self.push_test_result(name, result, reason, announced),
+                None => {
+                    self.pending_test = Some(PendingTest {
+                        name,
+                        announced,
+                        tentative_result: None,
+                    })
+                }
+            }
+        }
+        self.set_tentative_result(&text[last_end..]);
+
+        Ok(())
+    }
+
+    fn set_tentative_result(&mut self, output: &str) {
+        let Some(pending_test) = &mut self.pending_test else {
+            return;
+        };
+        let result = TRAILING_RESULT_REGEX.with(|r| {
+            r.captures(output)
+                .and_then(|c| c.name("result").expect("<result>").as_str().parse().ok())
+        });
+        if let Some(result) = result {
+            pending_test.tentative_result = Some((result, None));
+        }
+    }
+
+    fn finish_pending_test(&mut self) {
+        if let Some(PendingTest {
+            name,
+            announced,
+            tentative_result: Some((result, reason)),
+        }) = self.pending_test.take()
+        {
+            self.push_test_result(name, result, reason, announced);
+        }
+    }
+
+    fn push_test_result(
+        &mut self,
+        name: String,
+        kind: TestResultKind,
+        reason: Option<String>,
+        announced: bool,
+    ) {
+        let mut result = TestResultParseResult::new(name, kind, reason);
+        result.announced = announced;
+        self.test_results.push(result);
+    }
+
+    /// Reconciles the results found in the listing with the `failures:`
+    /// listing, which can't be mistaken for output printed by the tests, and
+    /// attaches the failure output to the failed tests.
+    ///
+    /// When tests run in parallel without capturing output, a passing result
+    /// printed by a test for a test that doesn't exist looks like the real
+    /// thing, unless it follows output of a test. When there are more passed
+    /// or ignored results than the summary counts, those that weren't
+    /// announced on a line of their own are dropped, earliest first, since a
+    /// test prints whatever it prints before its own result.
+    fn take_test_results(
+        &mut self,
+        test_summary: &TestSummary,
+    ) -> miette::Result<Vec<TestResultParseResult>> {
+        let failures_listing = std::mem::take(&mut self.failures_listing);
+        let failure_outputs = std::mem::take(&mut self.failure_outputs);
+        let panic_outputs = std::mem::take(&mut self.panic_outputs);
+        self.capturing_panic_output = false;
+        self.pending_test = None;
+
+        // The listing is only missing if the output was cut short.
+        let trust_listing = !failures_listing.is_empty() || test_summary.failed == 0;
+        let mut test_results: Vec<TestResultParseResult> = Vec::new();
+        for result in std::mem::take(&mut self.test_results) {
+            let listed = failures_listing
+                .iter()
+                .any(|n| is_same_test(n, &result.name));
+            if trust_listing && (result.kind == TestResultKind::Failed) != listed {
+                continue;
+            }
+
+            // A test may print a line that looks like its own result before
+            // its actual result, so the last one wins.
+            match test_results.iter_mut().find(|r| r.name == result.name) {
+                Some(existing) => *existing = result,
+                None => test_results.push(result),
             }
         }
-        Ok(self.into())
+        for kind in [TestResultKind::Ok, TestResultKind::Ignored] {
+            let count = match kind {
+                TestResultKind::Ok => test_summary.passed,
+                _ => test_summary.ignored,
+            };
+            let mut excess = test_results
+                .iter()
+                .filter(|r| r.kind == kind)
+                .count()
+                .saturating_sub(count);
+            test_results.retain(|r| {
+                let stray = excess > 0 && r.kind == kind && !r.announced;
+                excess -= usize::from(stray);
+                !stray
+            });
+        }
+        for name in failures_listing {
+            if !test_results.iter().any(|r| is_same_test(&name, &r.name)) {
+                test_results.push(TestResultParseResult::new(
+                    name,
+                    TestResultKind::Failed,
+                    None,
+                ));
+            }
+        }
+
+        for result in test_results
+            .iter_mut()
+            .filter(|r| r.kind == TestResultKind::Failed)
+        {
+            let output = failure_outputs
+                .iter()
+                .chain(&panic_outputs)
+                .find(|(name, _)| is_same_test(name, &result.name))
+                .map(|(_, output)| output);
+            result.failure_info = Some(match output {
+                Some(output) => TestFailureInfo::from_output(output)?,
+                None => TestFailureInfo::new(String::new(), None, String::new()),
+            });
+        }
+
+        Ok(test_results)
+    }
+
+    /// Parses the `---- <name> stdout ----` sections following a `failures:`
+    /// line, up to (but not including) the `failures:` line that starts the
+    /// listing of failed test names.
+    fn parse_failures(&mut self, lines: &mut TextLines) -> miette::Result<()> {
+        loop {
+            let Some(text) = lines.peek() else {
+                miette::bail!(
+                    "Encountered unexpected end of test output while parser was in state {:?}",
+                    self
+                );
+            };
+            if text.trim() == "failures:" {
+                return Ok(());
+            }
+
+            let name = FAILURE_HEADER_REGEX.with(|r| {
+                r.captures(text)
+                    .map(|c| c.name("name").expect("<name>").as_str().to_owned())
+            });
+            let _ = lines.next();
+            let Some(name) = name else {
+                continue;
+            };
+
+            let mut output = Vec::new();
+            while let Some(t) = lines.peek() {
+                if t.trim() == "failures:" || FAILURE_HEADER_REGEX.with(|r| r.is_match(t)) {
+                    break;
+                }
+                let _ = lines.next();
+                output.push(lines.last_raw().to_owned());
+            }
+            self.failure_outputs.push((name, output));
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+/// Strips ANSI escape sequences, such as colours, from `text`.
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    ANSI_ESCAPE_REGEX.with(|r| match r.replace_all(text, "") {
+        Cow::Borrowed(_) => Cow::Borrowed(text),
+        Cow::Owned(stripped) => Cow::Owned(stripped),
+    })
+}
+
+/// Whether `name`, as it appears in a failure header or listing, refers to the
+/// test listed as `test_name`. Doctests are listed with their attributes (e.g.
+/// `src/lib.rs - foo (line 3) - compile fail`), but are otherwise referred to
+/// without them.
+fn is_same_test(name: &str, test_name: &str) -> bool {
+    test_name == name
+        || test_name
+            .strip_prefix(name)
+            .is_some_and(|a| a.starts_with(" - "))
+}
+
+/// The text lines of a test run, with unlimited look-ahead.
+///
+/// Any ANSI escape sequences, such as the colours added by `--color always` or
+/// `CARGO_TERM_COLOR=always`, are stripped from the lines that are parsed. The
+/// lines as they were printed are kept for the output of the tests.
+#[derive(Debug)]
+struct TextLines {
+    lines: Vec<String>,
+    raw_lines: Vec<String>,
+    position: usize,
+}
+
+impl TextLines {
+    fn new(raw_lines: Vec<String>) -> Self {
+        let lines = raw_lines
+            .iter()
+            .map(|l| strip_ansi(l).into_owned())
+            .collect();
+        Self {
+            lines,
+            raw_lines,
+            position: 0,
+        }
+    }
+
+    /// The line most recently returned by `next`, as it was printed.
+    fn last_raw(&self) -> &str {
+        &self.raw_lines[self.position - 1]
+    }
+
+    fn get(&self, position: usize) -> Option<&str> {
+        self.lines.get(position).map(String::as_str)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.get(self.position)
+    }
+
+    /// Steps back to the line most recently returned by `next`.
+    fn unread(&mut self) {
+        self.position -= 1;
+    }
+
+    fn next_non_blank_position(&self, from: usize) -> usize {
+        self.find_position(from, |l| !l.trim().is_empty())
+            .unwrap_or(self.lines.len())
+    }
+
+    fn find_position(&self, from: usize, predicate: impl Fn(&str) -> bool) -> Option<usize> {
+        self.lines
+            .iter()
+            .skip(from)
+            .position(|l| predicate(l))
+            .map(|p| p + from)
+    }
+}
+
+impl Iterator for TextLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.get(self.position).cloned();
+        if line.is_some() {
+            self.position += 1;
+        }
+        line
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 struct TestDataParseResult {
     test_count: usize,
     test_results: Vec<TestResultParseResult>,
     test_summary: TestSummary,
+    target_times: Vec<TargetTime>,
 }
 
 impl TestDataParseResult {
@@ -464,57 +1144,71 @@ impl TestDataParseResult {
             test_count,
             test_results,
             test_summary,
+            target_times: Vec::new(),
         }
     }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.test_count += other.test_count;
+        self.test_results.extend(other.test_results);
+        self.target_times.extend(other.target_times);
+        self.test_summary = self.test_summary.merge(other.test_summary);
+        self
+    }
+
+    fn with_target(mut self, target: Option<&str>) -> Self {
+        for test_result in &mut self.test_results {
+            test_result.target = target.map(ToOwned::to_owned);
+        }
+        self.target_times = vec![TargetTime {
+            target: target.map(ToOwned::to_owned),
+            time: self.test_summary.total_time(),
+        }];
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum TestRunParserState {
     Initial,
     Tests,
-    FailuresOutput,
     FailuresListing,
     Results,
     Done,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum TestRunFailureParserState {
-    Initial,
-    Header,
-    Panic,
-    Stacktrace,
-    Done,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum TestRunParserPhase {
-    Tests,
-    DocTests,
-    Done,
-}
-
 #[derive(Clone, Debug)]
 struct TestResultParseResult {
     name: String,
     kind: TestResultKind,
+    ignore_reason: Option<String>,
     failure_info: Option<TestFailureInfo>,
+    target: Option<String>,
+    /// Whether the result was announced on a line of its own, rather than
+    /// after output of a test, which may have printed it.
+    announced: bool,
 }
 
 impl TestResultParseResult {
-    fn new(name: String, kind: TestResultKind) -> Self {
+    fn new(name: String, kind: TestResultKind, ignore_reason: Option<String>) -> Self {
         Self {
             name,
             kind,
+            ignore_reason,
             failure_info: None,
+            target: None,
+            announced: true,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TestResultKind {
+    #[default]
     Ok,
     Failed,
+    Ignored,
 }
 impl FromStr for TestResultKind {
     type Err = TestResultKindParseError;
@@ -523,6 +1217,7 @@ impl FromStr for TestResultKind {
         match s {
             "ok" => Ok(Self::Ok),
             "FAILED" => Ok(Self::Failed),
+            "ignored" => Ok(Self::Ignored),
             other => Err(TestResultKindParseError(other.into())),
         }
     }
@@ -532,25 +1227,155 @@ impl FromStr for TestResultKind {
 #[error("Unknown test result: {0}")]
 pub struct TestResultKindParseError(String);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestFailureInfo {
     pub panic_text: String,
-    pub location: TestFailureLocation,
+    /// Where the test panicked, if it panicked at all, and if the location
+    /// refers to a file in the source tree.
+    pub location: Option<TestFailureLocation>,
     pub stacktrace: String,
+    /// Everything the test printed, as captured by the test harness, including
+    /// any ANSI escape sequences.
+    #[serde(default)]
+    pub output: String,
 }
 
 impl TestFailureInfo {
-    fn new(panic_text: String, location: TestFailureLocation, stacktrace: String) -> Self {
+    fn new(panic_text: String, location: Option<TestFailureLocation>, stacktrace: String) -> Self {
         Self {
             panic_text,
             location,
             stacktrace,
+            output: String::new(),
+        }
+    }
+
+    /// Classifies the failure of a test, or of a doctest if `is_doctest`.
+    pub fn kind(&self, is_doctest: bool) -> FailureKind {
+        let output = strip_ansi(&self.output);
+        let panicked = PANIC_HEADER_REGEX.with(|r| output.lines().any(|l| r.is_match(l)));
+        if TIMEOUT_REGEX.with(|r| r.is_match(&self.panic_text) || r.is_match(&output)) {
+            FailureKind::Timeout
+        } else if panicked {
+            if self.panic_text.starts_with("assertion ") {
+                FailureKind::Assertion
+            } else {
+                FailureKind::Panic
+            }
+        } else if CRASH_REGEX.with(|r| r.is_match(&self.panic_text)) {
+            FailureKind::Crash
+        } else if is_doctest {
+            FailureKind::DoctestCompile
+        } else {
+            FailureKind::Panic
+        }
+    }
+
+    /// The values compared by a failed `assert_eq!` or `assert_ne!`, left
+    /// first.
+    pub fn assertion_values(&self) -> Option<(String, String)> {
+        let (mut left, mut right) = (None, None);
+        for line in self.panic_text.lines() {
+            let Some(c) = ASSERTION_VALUE_REGEX.with(|r| r.captures(line)) else {
+                continue;
+            };
+            let value = c.name("value").expect("<value>").as_str().to_owned();
+            match c.name("side").expect("<side>").as_str() {
+                "left" => left = left.or(Some(value)),
+                _ => right = right.or(Some(value)),
+            }
         }
+        Some((left?, right?))
+    }
+
+    /// The frames of the stack backtrace, innermost first.
+    pub fn backtrace_frames(&self) -> Vec<BacktraceFrame> {
+        let mut frames: Vec<BacktraceFrame> = Vec::new();
+        for line in self.stacktrace.lines().skip(1) {
+            if let Some(location) = line.trim().strip_prefix("at ") {
+                if let Some(frame) = frames.last_mut() {
+                    frame.location = location.parse().ok();
+                }
+            } else if let Some(c) = BACKTRACE_FRAME_REGEX.with(|r| r.captures(line)) {
+                frames.push(BacktraceFrame {
+                    function: c["function"].to_owned(),
+                    location: None,
+                });
+            }
+        }
+        frames
+    }
+
+    /// Builds the failure info from the lines of a test's `---- <name> stdout ----`
+    /// section, as they were printed.
+    fn from_output(raw_output: &[String]) -> miette::Result<Self> {
+        let raw_output = raw_output.join("\n").trim_matches('\n').to_owned();
+        let output: Vec<_> = raw_output
+            .lines()
+            .map(|l| strip_ansi(l).into_owned())
+            .collect();
+        let panic_position = PANIC_HEADER_REGEX.with(|r| output.iter().position(|l| r.is_match(l)));
+        let Some(panic_position) = panic_position else {
+            // Tests can fail without panicking, e.g. by returning an `Err`, or,
+            // in the case of doctests, by failing to compile (or compiling when
+            // marked `compile_fail`).
+            return Ok(Self {
+                output: raw_output,
+                ..Self::new(output.join("\n").trim().to_owned(), None, String::new())
+            });
+        };
+
+        let panic_end = output
+            .iter()
+            .skip(panic_position + 1)
+            .position(|l| l.trim() == "stack backtrace:" || l.starts_with("note: "))
+            .map_or(output.len(), |p| p + panic_position + 1);
+        let panic_output = output[panic_position..panic_end].join("\n");
+        let (panic_text, location) = PANIC_REGEX.with(|r| -> miette::Result<_> {
+            let c = r
+                .captures(&panic_output)
+                .ok_or_else(|| miette::miette!("Unknown panic format: {}", panic_output))?;
+            let message = c
+                .name("message")
+                .or_else(|| c.name("old_message"))
+                .map_or("", |m| m.as_str());
+            let location = c
+                .name("location")
+                .or_else(|| c.name("old_location"))
+                .expect("<location>")
+                .as_str();
+            Ok((
+                message.trim_end().to_owned(),
+                location.parse::<TestFailureLocation>()?,
+            ))
+        })?;
+
+        // Merged doctests (Rust 2024 edition and later) panic inside a
+        // generated bundle in a temporary directory, so the location is of no
+        // use to anyone.
+        let location = Some(location).filter(|l| {
+            !l.file
+                .rsplit(['/', '\\'])
+                .next()
+                .is_some_and(|f| f.starts_with("doctest_bundle_"))
+        });
+
+        let stacktrace = output
+            .iter()
+            .position(|l| l.trim() == "stack backtrace:")
+            .map(|p| output[p..].join("\n").trim_end().to_owned())
+            .unwrap_or_default();
+
+        Ok(Self {
+            output: raw_output,
+            ..Self::new(panic_text, location, stacktrace)
+        })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestFailureLocation {
+    /// The path of the file, with `/` as the separator regardless of platform.
     pub file: String,
     pub line: u64,
     pub column: u64,
@@ -560,21 +1385,24 @@ impl FromStr for TestFailureLocation {
     type Err = TestFailureLocationParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split(':');
-        let file = parts
+        // Parse from the right, since Windows paths may contain a drive letter
+        // followed by a colon.
+        let mut parts = s.rsplitn(3, ':');
+        let column: u64 = parts
             .next()
             .ok_or_else(|| TestFailureLocationParseError(s.to_owned()))?
-            .to_owned();
+            .parse()
+            .map_err(|_| TestFailureLocationParseError(s.to_owned()))?;
         let line: u64 = parts
             .next()
             .ok_or_else(|| TestFailureLocationParseError(s.to_owned()))?
             .parse()
             .map_err(|_| TestFailureLocationParseError(s.to_owned()))?;
-        let column: u64 = parts
+        let file = parts
             .next()
+            .filter(|f| !f.is_empty())
             .ok_or_else(|| TestFailureLocationParseError(s.to_owned()))?
-            .parse()
-            .map_err(|_| TestFailureLocationParseError(s.to_owned()))?;
+            .replace('\\', "/");
 
         Ok(Self { file, line, column })
     }
@@ -584,7 +1412,62 @@ impl FromStr for TestFailureLocation {
 #[error("Unknown location format: {0}")]
 pub struct TestFailureLocationParseError(String);
 
+/// What made a test fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The test panicked.
+    Panic,
+    /// An assertion in the test failed.
+    Assertion,
+    /// The test took longer than it was allowed to.
+    Timeout,
+    /// The test process exited without the test finishing, e.g. because of a
+    /// signal.
+    Crash,
+    /// A doctest failed to compile, or compiled when it shouldn't have.
+    DoctestCompile,
+}
+
+impl FailureKind {
+    pub const ALL: [Self; 5] = [
+        Self::Panic,
+        Self::Assertion,
+        Self::Timeout,
+        Self::Crash,
+        Self::DoctestCompile,
+    ];
+
+    /// A stable identifier for the kind of failure.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Panic => "panic",
+            Self::Assertion => "assertion",
+            Self::Timeout => "timeout",
+            Self::Crash => "crash",
+            Self::DoctestCompile => "doctest-compile",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Panic => "The test panicked",
+            Self::Assertion => "An assertion in the test failed",
+            Self::Timeout => "The test exceeded its time limit",
+            Self::Crash => "The test process crashed",
+            Self::DoctestCompile => {
+                "The doctest failed to compile, or compiled despite being marked `compile_fail`"
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+pub struct BacktraceFrame {
+    pub function: String,
+    pub location: Option<TestFailureLocation>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TestSummary {
     pub result: TestResultKind,
     pub passed: usize,
@@ -592,10 +1475,13 @@ pub struct TestSummary {
     pub ignored: usize,
     pub measured: usize,
     pub filtered: usize,
-    pub time: String,
+    pub time: Duration,
+    /// How long it took to compile merged doctests (Rust 2024 edition and later).
+    pub merged_compilation_time: Option<Duration>,
 }
 
 impl TestSummary {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         result: TestResultKind,
         passed: usize,
@@ -603,7 +1489,8 @@ impl TestSummary {
         ignored: usize,
         measured: usize,
         filtered: usize,
-        time: String,
+        time: Duration,
+        merged_compilation_time: Option<Duration>,
     ) -> Self {
         Self {
             result,
@@ -613,13 +1500,65 @@ impl TestSummary {
             measured,
             filtered,
             time,
+            merged_compilation_time,
         }
     }
+
+    /// The time spent running the tests, plus the time it took to compile
+    /// merged doctests, if any.
+    pub fn total_time(&self) -> Duration {
+        self.time + self.merged_compilation_time.unwrap_or_default()
+    }
+
+    fn merge(self, other: Self) -> Self {
+        let result =
+            if self.result == TestResultKind::Failed || other.result == TestResultKind::Failed {
+                TestResultKind::Failed
+            } else {
+                TestResultKind::Ok
+            };
+        let merged_compilation_time =
+            match (self.merged_compilation_time, other.merged_compilation_time) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+        Self::new(
+            result,
+            self.passed + other.passed,
+            self.failed + other.failed,
+            self.ignored + other.ignored,
+            self.measured + other.measured,
+            self.filtered + other.filtered,
+            self.time + other.time,
+            merged_compilation_time,
+        )
+    }
 }
 
 thread_local! {
+    // CSI sequences (colours, cursor movement), OSC sequences (hyperlinks,
+    // window titles), character set designations and other two-byte escapes.
+    static ANSI_ESCAPE_REGEX: Regex = Regex::new(r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(?:\x07|\x1b\\)|[()][0-9A-Za-z]|[@-Z\\-_])").unwrap();
     static RUNNING_REGEX: Regex = Regex::new(r"running (?P<count>\d+) tests?").unwrap();
-    static TEST_REGEX: Regex = Regex::new(r"test (?P<name>.+?) ... (?P<result>ok|FAILED)").unwrap();
-    static RESULT_REGEX: Regex = Regex::new(r"test result: (?P<result>ok|FAILED). (?P<passed>\d+) passed; (?P<failed>\d+) failed; (?P<ignored>\d+) ignored; (?P<measured>\d+) measured; (?P<filtered>\d+) filtered out; finished in (?P<time>.+)").unwrap();
+    // The result is optional, since it's printed after the test finishes
+    // when tests run on a single thread.
+    static TEST_REGEX: Regex = Regex::new(r"test (?P<name>.+?) \.\.\. (?:(?P<result>ok|FAILED|ignored)(?:, (?P<reason>.+))?$)?").unwrap();
+    static BARE_RESULT_REGEX: Regex = Regex::new(r"^\s*(?P<result>ok|FAILED|ignored)(?:, (?P<reason>.+))?\s*$").unwrap();
+    static TRAILING_RESULT_REGEX: Regex = Regex::new(r"(?P<result>ok|FAILED)\s*$").unwrap();
+    static RESULT_REGEX: Regex = Regex::new(r"test result: (?P<result>ok|FAILED). (?P<passed>\d+) passed; (?P<failed>\d+) failed; (?P<ignored>\d+) ignored; (?P<measured>\d+) measured; (?P<filtered>\d+) filtered out; finished in (?P<time>\d+(?:\.\d+)?)s(?:; merged doctests compilation took (?P<merged_compilation_time>\d+(?:\.\d+)?)s)?").unwrap();
     static FAILURE_HEADER_REGEX: Regex = Regex::new(r"---- (?P<name>.+?) stdout ----").unwrap();
+    static RUNNING_BINARY_REGEX: Regex = Regex::new(r"^\s*Running (?:unittests )?(?:(?P<src_path>.+) \((?P<executable>.+)\)|(?P<bare_executable>\S+))$").unwrap();
+    static DOC_TESTS_REGEX: Regex = Regex::new(r"^\s*Doc-tests (?P<crate_name>\S+)$").unwrap();
+    static ALL_DOCTESTS_REGEX: Regex = Regex::new(r"all doctests ran in (?P<time>\d+(?:\.\d+)?)s; merged doctests compilation took (?P<merged_compilation_time>\d+(?:\.\d+)?)s").unwrap();
+    static ASSERTION_VALUE_REGEX: Regex = Regex::new(r"^\s*(?P<side>left|right): (?P<value>.*)$").unwrap();
+    // Inlined frames don't have an index of their own.
+    static BACKTRACE_FRAME_REGEX: Regex = Regex::new(r"^(?:\s*\d+: |\s+)(?P<function>\S.*?)\s*$").unwrap();
+    static TIMEOUT_REGEX: Regex = Regex::new(r"time limit exceeded|timed out").unwrap();
+    // How rustdoc and cargo report processes that were killed or exited
+    // without a result.
+    static CRASH_REGEX: Regex = Regex::new(r"\((?:signal|exit status|exit code): ").unwrap();
+    static PANIC_HEADER_REGEX: Regex = Regex::new(r"^thread '(?P<thread>[^']*)'(?: \(\d+\))? panicked at ").unwrap();
+    // Since Rust 1.73, the panic message follows the location on the next
+    // line(s). Before that, it preceded the location on the same line.
+    static PANIC_REGEX: Regex = Regex::new(r"(?s)^thread '[^']*'(?: \(\d+\))? panicked at (?:'(?P<old_message>.*)', (?P<old_location>[^\n]+)|(?P<location>[^\n]+):(?:\n(?P<message>.*))?)$").unwrap();
 }
@@ -16,187 +16,756 @@
 //
 // Licensed under the MIT License. See LICENSE-MIT for details.
 
-use cargo_metadata::MetadataCommand;
-use cargo_test_annotations::{parse_capture, TestResultValue};
+use cargo_metadata::{Metadata, MetadataCommand};
+use cargo_test_annotations::annotations::{self, annotated_failures, Annotation, AnnotationLevel};
+use cargo_test_annotations::baseline::{Baseline, FailureStatus};
+use cargo_test_annotations::config::{Config, ExpiredQuarantine};
+use cargo_test_annotations::paths::RepoPaths;
+use cargo_test_annotations::run;
+use cargo_test_annotations::source::SourceTree;
+use cargo_test_annotations::summary::BlobLinks;
+use cargo_test_annotations::TestRun;
 use chrono::Utc;
+use clap::builder::Resettable;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use miette::{Context, IntoDiagnostic};
 use octocrab::params::checks::{
     CheckRunConclusion, CheckRunOutput, CheckRunOutputAnnotation, CheckRunOutputAnnotationLevel,
     CheckRunStatus,
 };
 use octocrab::OctocrabBuilder;
-use regex::Regex;
+use std::ffi::OsString;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Parse Cargo test output and report the test failures.
+///
+/// Every option can also be given through the `INPUT_*` environment variable
+/// the GitHub Action passes it in. Without a subcommand, `github` is run.
+#[derive(Parser, Debug)]
+#[command(
+    name = "cargo-test-annotations",
+    bin_name = "cargo test-annotations",
+    version
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the test failures to the terminal
+    Report {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Run `cargo test`, print its test failures and exit like it did
+    Run {
+        /// Path of the Cargo workspace root relative to the repository root;
+        /// detected with git if not given
+        #[arg(long, env = "INPUT_PATH_PREFIX")]
+        path_prefix: Option<String>,
+        /// Arguments to `cargo test`
+        #[arg(last = true)]
+        args: Vec<OsString>,
+    },
+    /// Report the test failures to GitHub, like the GitHub Action does
+    Github(GithubArgs),
+    /// Render a Markdown summary of the test results, or workflow commands
+    Render {
+        #[command(flatten)]
+        input: InputArgs,
+        /// What to render
+        #[arg(long, value_enum, default_value_t = RenderFormat::Markdown)]
+        to: RenderFormat,
+        /// Title of the summary
+        #[arg(long, env = "INPUT_NAME", default_value = "cargo-test-annotations")]
+        name: String,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert the test results to another format
+    Convert {
+        #[command(flatten)]
+        input: InputArgs,
+        /// The format to convert to
+        #[arg(long, value_enum)]
+        to: ConvertFormat,
+        /// Name of the test suites, for JUnit XML
+        #[arg(long, env = "INPUT_NAME", default_value = "cargo-test-annotations")]
+        name: String,
+        /// File to write to, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct InputArgs {
+    /// Files with the output of `cargo test`, or glob patterns matching them;
+    /// standard input if none are given, or for `-`
+    #[arg(env = "INPUT_TESTS", value_delimiter = '\n')]
+    tests: Vec<String>,
+    /// Format of the test output
+    #[arg(long, value_enum, env = "INPUT_FORMAT", default_value_t = InputFormat::Json)]
+    format: InputFormat,
+    /// File with the output of `cargo metadata --format-version 1`; `cargo
+    /// metadata` is run if not given
+    #[arg(long, env = "INPUT_METADATA")]
+    metadata: Option<PathBuf>,
+    /// Path of the Cargo workspace root relative to the repository root;
+    /// detected with git if not given
+    #[arg(long, env = "INPUT_PATH_PREFIX")]
+    path_prefix: Option<String>,
+    /// Test results to compare with, like those of the merge base, to tell
+    /// new failures from old ones
+    #[arg(long, env = "INPUT_BASELINE")]
+    baseline: Option<PathBuf>,
+    /// Format of the baseline
+    #[arg(long, value_enum, env = "INPUT_BASELINE_FORMAT", default_value_t = InputFormat::Dump)]
+    baseline_format: InputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    /// The output of `cargo test --message-format json`
+    Json,
+    /// The regular output of `cargo test`, stdout and stderr
+    Text,
+    /// Test results previously converted with `--to dump`
+    Dump,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum RenderFormat {
+    Markdown,
+    WorkflowCommands,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ConvertFormat {
+    /// Versioned JSON, for caching or consumption by other tools
+    Dump,
+    Junit,
+    Tap,
+    /// SARIF 2.1.0, for GitHub code scanning
+    Sarif,
+    /// A GitLab Code Quality report
+    Gitlab,
+    /// reviewdog diagnostics (`-f=rdjsonl`)
+    Rdjsonl,
+    Checkstyle,
+}
+
+#[derive(Args, Debug)]
+struct GithubArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// How to report the annotations
+    #[arg(long, value_enum, env = "INPUT_OUTPUT", default_value_t = GithubOutput::CheckRun)]
+    output: GithubOutput,
+    /// Whether to write a Markdown job summary to `$GITHUB_STEP_SUMMARY`
+    #[arg(long, env = "INPUT_SUMMARY", default_value_t = true, action = ArgAction::Set)]
+    summary: bool,
+    /// The `GITHUB_TOKEN` secret of the workflow, for the `check-run` output
+    #[arg(long, env = "INPUT_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Display name of the check run
+    #[arg(long, env = "INPUT_NAME", default_value = "cargo-test-annotations")]
+    name: String,
+    /// Only annotate failures that aren't in the baseline
+    #[arg(long, env = "INPUT_ONLY_NEW", action = ArgAction::SetTrue)]
+    only_new: bool,
+    #[arg(
+        long,
+        env = "INPUT_DUMP",
+        help = "Also write the test results as a dump"
+    )]
+    dump: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "INPUT_JUNIT",
+        help = "Also write the test results as JUnit XML"
+    )]
+    junit: Option<PathBuf>,
+    #[arg(long, env = "INPUT_TAP", help = "Also write the test results as TAP")]
+    tap: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "INPUT_SARIF",
+        help = "Also write the test failures as SARIF"
+    )]
+    sarif: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "INPUT_GITLAB",
+        help = "Also write the test failures as a GitLab Code Quality report"
+    )]
+    gitlab: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "INPUT_RDJSONL",
+        help = "Also write the test failures as reviewdog diagnostics"
+    )]
+    rdjsonl: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "INPUT_CHECKSTYLE",
+        help = "Also write the test failures as Checkstyle XML"
+    )]
+    checkstyle: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum GithubOutput {
+    /// Create a check run through the Checks API
+    CheckRun,
+    /// Print workflow commands, which needs no token
+    WorkflowCommands,
+    /// Only write the job summary and files
+    None,
+}
+
+/// Test runs, along with what's needed to annotate them.
+struct Input {
+    test_runs: Vec<TestRun>,
+    repo_paths: RepoPaths,
+    baseline: Option<Baseline>,
+    config: Config,
+}
+
+impl Input {
+    fn annotations(&self) -> miette::Result<Vec<Annotation>> {
+        let mut annotations = annotations::annotations(
+            &self.test_runs,
+            &SourceTree::new(&self.repo_paths),
+            &self.config.templates,
+        )?;
+        let expired = self
+            .config
+            .quarantine(&mut annotations, Utc::now().date_naive());
+        for ExpiredQuarantine { test, expired } in expired {
+            eprintln!(
+                "warning: the quarantine of {} expired on {}; annotating it as usual",
+                test, expired
+            );
+        }
+        Ok(annotations)
+    }
+
+    fn total_time(&self) -> Duration {
+        self.test_runs.iter().map(|r| r.total_time()).sum()
+    }
+}
+
+impl InputArgs {
+    fn read(&self) -> miette::Result<Input> {
+        let metadata = self.metadata()?;
+        let repo_paths = repo_paths(&metadata, self.path_prefix.as_deref());
+        let config = Config::load(&metadata)?;
+
+        let patterns: Vec<_> = self
+            .tests
+            .iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .collect();
+        let mut test_runs = Vec::new();
+        if patterns.is_empty() {
+            test_runs = self.parse_stdin(metadata.clone())?;
+        }
+        for pattern in patterns {
+            if pattern == "-" {
+                test_runs.extend(self.parse_stdin(metadata.clone())?);
+                continue;
+            }
+            test_runs.extend(cargo_test_annotations::parse_files(pattern, |file| {
+                self.parse(self.format, file, metadata.clone())
+            })?);
+        }
+
+        Ok(Input {
+            test_runs,
+            repo_paths,
+            baseline: self.read_baseline(metadata)?,
+            config,
+        })
+    }
+
+    fn parse_stdin(&self, metadata: Metadata) -> miette::Result<Vec<TestRun>> {
+        let stdin = std::io::stdin();
+        if stdin.is_terminal() {
+            miette::bail!(
+                help = "pipe the output of `cargo test` in, or pass the file it was saved to",
+                "no test output to read"
+            );
+        }
+        self.parse(self.format, stdin.lock(), metadata)
+    }
+
+    fn read_baseline(&self, metadata: Metadata) -> miette::Result<Option<Baseline>> {
+        let Some(path) = &self.baseline else {
+            return Ok(None);
+        };
+        let file = std::fs::File::open(path)
+            .into_diagnostic()
+            .with_context(|| path.display().to_string())?;
+        let test_runs = self
+            .parse(self.baseline_format, file, metadata)
+            .with_context(|| path.display().to_string())?;
+        Ok(Some(Baseline::new(&test_runs)))
+    }
+
+    fn metadata(&self) -> miette::Result<Metadata> {
+        match &self.metadata {
+            Some(path) => MetadataCommand::parse(
+                std::fs::read_to_string(path)
+                    .into_diagnostic()
+                    .with_context(|| path.display().to_string())?,
+            )
+            .into_diagnostic()
+            .with_context(|| path.display().to_string()),
+            None => MetadataCommand::new()
+                .no_deps()
+                .exec()
+                .into_diagnostic()
+                .wrap_err("could not run `cargo metadata`; pass its output with `--metadata`"),
+        }
+    }
+
+    fn parse<R: std::io::Read>(
+        &self,
+        format: InputFormat,
+        r: R,
+        metadata: Metadata,
+    ) -> miette::Result<Vec<TestRun>> {
+        match format {
+            InputFormat::Json => cargo_test_annotations::parse(r, metadata),
+            InputFormat::Text => cargo_test_annotations::parse_text(r, metadata),
+            InputFormat::Dump => cargo_test_annotations::dump::read(r),
+        }
+    }
+}
+
+fn repo_paths(metadata: &Metadata, path_prefix: Option<&str>) -> RepoPaths {
+    match path_prefix {
+        Some(path_prefix) => RepoPaths::with_path_prefix(metadata, path_prefix),
+        None => RepoPaths::discover(metadata),
+    }
+}
+
+/// The command line arguments, adjusted for how we were invoked.
+fn args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    // Cargo passes the name of the subcommand on when run as `cargo
+    // test-annotations`.
+    if args.get(1).is_some_and(|a| a == "test-annotations") {
+        args.remove(1);
+    }
+    // The GitHub Action passes its inputs through the environment only.
+    if args.len() == 1 {
+        args.push("github".into());
+    }
+    args
+}
+
+/// Parses the command line. The GitHub Action sets the `INPUT_*` variable of
+/// every input, to an empty string if it isn't given, so empty ones are
+/// ignored.
+fn cli() -> Cli {
+    let command = Cli::command().mut_subcommands(|subcommand| {
+        subcommand.mut_args(|arg| {
+            let empty = arg
+                .get_env()
+                .and_then(std::env::var_os)
+                .is_some_and(|value| value.is_empty());
+            if empty {
+                arg.env(Resettable::Reset)
+            } else {
+                arg
+            }
+        })
+    });
+    Cli::from_arg_matches(&command.get_matches_from(args())).unwrap_or_else(|e| e.exit())
+}
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
-    let metadata = std::env::var("INPUT_METADATA").expect("`metadata` input value missing");
-    let tests = std::env::var("INPUT_TESTS").expect("`tests` input value missing");
-    let token = std::env::var("INPUT_TOKEN").expect("`token` input value missing");
-    let name = std::env::var("INPUT_NAME").expect("`name` input value missing");
+    match cli().command {
+        Command::Report { input } => report(&input.read()?),
+        Command::Run { path_prefix, args } => {
+            let mut metadata_command = MetadataCommand::new();
+            metadata_command.no_deps();
+            if let Some(manifest_path) = run::manifest_path(&args) {
+                metadata_command.manifest_path(manifest_path);
+            }
+            let metadata = metadata_command
+                .exec()
+                .into_diagnostic()
+                .wrap_err("could not run `cargo metadata`")?;
+            let repo_paths = repo_paths(&metadata, path_prefix.as_deref());
+            let config = Config::load(&metadata)?;
+            let (test_runs, status) = run::cargo_test(&args, metadata)?;
+            let reported = test_runs.and_then(|test_runs| {
+                report(&Input {
+                    test_runs,
+                    repo_paths,
+                    baseline: None,
+                    config,
+                })
+            });
+            // The tests' outcome matters more than ours.
+            if let Err(e) = reported {
+                eprintln!("{:?}", e);
+            }
+            std::process::exit(status.code().unwrap_or(1))
+        }
+        Command::Github(args) => github(args).await,
+        Command::Render {
+            input,
+            to,
+            name,
+            output,
+        } => {
+            let input = input.read()?;
+            let annotations = input.annotations()?;
+            write_output(output.as_deref(), |w| match to {
+                RenderFormat::Markdown => cargo_test_annotations::summary::write(
+                    w,
+                    &name,
+                    &input.test_runs,
+                    &annotations,
+                    BlobLinks::from_env().as_ref(),
+                    input.baseline.as_ref(),
+                )
+                .into_diagnostic(),
+                RenderFormat::WorkflowCommands => {
+                    cargo_test_annotations::workflow::write(w, &annotations).into_diagnostic()
+                }
+            })
+        }
+        Command::Convert {
+            input,
+            to,
+            name,
+            output,
+        } => {
+            let input = input.read()?;
+            let annotations = input.annotations()?;
+            write_output(output.as_deref(), |w| {
+                convert(w, to, &name, &input, &annotations)
+            })
+        }
+    }
+}
 
-    let metadata = MetadataCommand::parse(
-        std::fs::read_to_string(&metadata)
-            .into_diagnostic()
-            .with_context(|| metadata)?,
+/// Calls `write` with `path`, or standard output if there's none.
+fn write_output(
+    path: Option<&Path>,
+    write: impl FnOnce(&mut dyn Write) -> miette::Result<()>,
+) -> miette::Result<()> {
+    match path {
+        Some(path) => {
+            let mut file = std::fs::File::create(path)
+                .into_diagnostic()
+                .with_context(|| path.display().to_string())?;
+            write(&mut file).with_context(|| path.display().to_string())
+        }
+        None => write(&mut std::io::stdout().lock()),
+    }
+}
+
+fn convert(
+    w: &mut dyn Write,
+    to: ConvertFormat,
+    name: &str,
+    input: &Input,
+    annotations: &[Annotation],
+) -> miette::Result<()> {
+    let test_runs = &input.test_runs;
+    match to {
+        ConvertFormat::Dump => cargo_test_annotations::dump::write(w, test_runs),
+        ConvertFormat::Junit => {
+            cargo_test_annotations::junit::write(w, name, test_runs).into_diagnostic()
+        }
+        ConvertFormat::Tap => {
+            cargo_test_annotations::tap::write(w, test_runs, annotations).into_diagnostic()
+        }
+        ConvertFormat::Sarif => {
+            cargo_test_annotations::sarif::write(w, test_runs, annotations, &input.repo_paths)
+        }
+        ConvertFormat::Gitlab => cargo_test_annotations::gitlab::write(w, test_runs, annotations),
+        ConvertFormat::Rdjsonl => {
+            cargo_test_annotations::reviewdog::write_rdjsonl(w, test_runs, annotations)
+        }
+        ConvertFormat::Checkstyle => {
+            cargo_test_annotations::reviewdog::write_checkstyle(w, test_runs, annotations)
+                .into_diagnostic()
+        }
+    }
+}
+
+fn report(input: &Input) -> miette::Result<()> {
+    let annotations = input.annotations()?;
+    let mut stdout = std::io::stdout().lock();
+    let mut failures = 0;
+    let mut warnings = 0;
+    for failure in annotated_failures(&input.test_runs, &annotations) {
+        let level = match failure.annotation.map(|a| a.level) {
+            None | Some(AnnotationLevel::Failure) => {
+                failures += 1;
+                "error"
+            }
+            Some(AnnotationLevel::Warning) => {
+                warnings += 1;
+                "warning"
+            }
+            Some(AnnotationLevel::Notice) => "note",
+        };
+        write!(stdout, "{}: {} failed", level, failure.result.name).into_diagnostic()?;
+        write!(stdout, " in {}", failure.test_run.package.name).into_diagnostic()?;
+        if !failure.test_run.features.is_empty() {
+            write!(
+                stdout,
+                " with features {}",
+                failure.test_run.features.join(", ")
+            )
+            .into_diagnostic()?;
+        }
+        match input.baseline.as_ref().map(|b| {
+            b.status(
+                &failure.test_run.package.name,
+                &failure.test_run.features,
+                &failure.result.name,
+            )
+        }) {
+            Some(FailureStatus::New) => write!(stdout, " (new)").into_diagnostic()?,
+            Some(FailureStatus::StillFailing) => {
+                write!(stdout, " (still failing)").into_diagnostic()?
+            }
+            None => {}
+        }
+        if failure.annotation.is_some_and(|a| a.flaky) {
+            write!(stdout, " (flaky)").into_diagnostic()?;
+        }
+        writeln!(stdout).into_diagnostic()?;
+        if let Some(annotation) = failure.annotation {
+            writeln!(stdout, "  --> {}", annotation.target).into_diagnostic()?;
+        }
+        for line in failure.failure.panic_text.lines() {
+            writeln!(stdout, "    {}", line).into_diagnostic()?;
+        }
+        writeln!(stdout).into_diagnostic()?;
+    }
+    for fixed in input
+        .baseline
+        .iter()
+        .flat_map(|b| b.fixed(&input.test_runs))
+    {
+        writeln!(stdout, "fixed: {} in {}", fixed.name, fixed.package).into_diagnostic()?;
+    }
+    writeln!(
+        stdout,
+        "{}",
+        summary_line(failures, warnings, input.total_time())
     )
-    .into_diagnostic()?;
+    .into_diagnostic()
+}
 
-    let test_output_file = std::fs::File::open(&tests)
+async fn github(args: GithubArgs) -> miette::Result<()> {
+    if args.only_new && args.input.baseline.is_none() {
+        miette::bail!(
+            help = "pass the test results of the merge base with `--baseline`",
+            "telling new failures from old ones needs a baseline"
+        );
+    }
+    let input = args.input.read()?;
+    let annotations = input.annotations()?;
+    for (path, format) in [
+        (&args.dump, ConvertFormat::Dump),
+        (&args.junit, ConvertFormat::Junit),
+        (&args.tap, ConvertFormat::Tap),
+        (&args.sarif, ConvertFormat::Sarif),
+        (&args.gitlab, ConvertFormat::Gitlab),
+        (&args.rdjsonl, ConvertFormat::Rdjsonl),
+        (&args.checkstyle, ConvertFormat::Checkstyle),
+    ] {
+        if let Some(path) = path {
+            write_output(Some(path), |w| {
+                convert(w, format, &args.name, &input, &annotations)
+            })?;
+        }
+    }
+
+    let total_time = input.total_time();
+    let test_runs = input.test_runs;
+    let name = args.name;
+    if let Some(step_summary) = std::env::var("GITHUB_STEP_SUMMARY")
+        .ok()
+        .filter(|s| args.summary && !s.is_empty())
+    {
+        let step_summary_file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&step_summary)
+            .into_diagnostic()
+            .with_context(|| step_summary.clone())?;
+        cargo_test_annotations::summary::write(
+            step_summary_file,
+            &name,
+            &test_runs,
+            &annotations,
+            BlobLinks::from_env().as_ref(),
+            input.baseline.as_ref(),
+        )
         .into_diagnostic()
-        .with_context(|| tests)?;
+        .with_context(|| step_summary)?;
+    }
+    if args.output == GithubOutput::None {
+        return Ok(());
+    }
+    let annotations: Vec<_> = match &input.baseline {
+        Some(baseline) if args.only_new => annotations
+            .into_iter()
+            .filter(|a| {
+                a.feature_sets
+                    .iter()
+                    .any(|f| baseline.status(&a.package, f, &a.test) == FailureStatus::New)
+            })
+            .collect(),
+        _ => annotations,
+    };
+    if args.output == GithubOutput::WorkflowCommands {
+        cargo_test_annotations::workflow::write(std::io::stdout().lock(), &annotations)
+            .into_diagnostic()?;
+        println!("{}", annotations_summary(&annotations, total_time));
+        return Ok(());
+    }
 
+    let token = args.token.ok_or_else(|| {
+        miette::miette!(
+            help = "pass `--token`, or use `--output workflow-commands`, which needs none",
+            "the `check-run` output needs a GitHub token"
+        )
+    })?;
+    let repo = github_env("GITHUB_REPOSITORY")?;
+    let (owner, repo) = repo.split_once('/').ok_or_else(|| {
+        miette::miette!(
+            "GITHUB_REPOSITORY is not of the form `owner/repo`: {}",
+            repo
+        )
+    })?;
+    let sha = github_env("GITHUB_SHA")?;
     let octocrab = octocrab::initialise(
         OctocrabBuilder::new()
             .user_access_token(token)
             .build()
-            .expect("valid configuration"),
+            .into_diagnostic()?,
     );
 
-    let test_runs = cargo_test_annotations::parse(test_output_file, metadata)?;
-    let mut annotations = Vec::new();
-    for test_run in test_runs
+    // Flaky and quarantined tests are only warned about.
+    let failed = annotations
+        .iter()
+        .any(|a| a.level == AnnotationLevel::Failure);
+    let summary = annotations_summary(&annotations, total_time);
+    let annotations: Vec<_> = annotations
         .into_iter()
-        .filter(|r| r.test_run.test_count != 0 || r.doc_test_run.test_count != 0)
-        .filter(|r| {
-            r.test_run
-                .test_results
-                .iter()
-                .any(|t| matches!(t.result, TestResultValue::Failed(_)))
-                || r.doc_test_run
-                    .test_results
-                    .iter()
-                    .any(|t| matches!(t.result, TestResultValue::Failed(_)))
+        .map(|a| CheckRunOutputAnnotation {
+            annotation_level: match a.level {
+                AnnotationLevel::Failure => CheckRunOutputAnnotationLevel::Failure,
+                AnnotationLevel::Warning => CheckRunOutputAnnotationLevel::Warning,
+                AnnotationLevel::Notice => CheckRunOutputAnnotationLevel::Notice,
+            },
+            path: a.target.path,
+            start_line: a.target.line as u32,
+            end_line: a.target.line as u32,
+            start_column: a.target.column.map(|c| c as u32),
+            end_column: None,
+            message: a.message,
+            title: Some(a.title),
+            raw_details: Some(a.raw_details),
         })
-    {
-        let features = test_run.features;
+        .collect();
 
-        for result in test_run
-            .test_run
-            .test_results
-            .into_iter()
-            .filter(|t| matches!(t.result, TestResultValue::Failed(_)))
-        {
-            let failure = result.result.unwrap_failure_ref();
-            let location = &failure.location;
-
-            annotations.push(CheckRunOutputAnnotation {
-                annotation_level: CheckRunOutputAnnotationLevel::Failure,
-                path: location.file.clone(),
-                start_line: location.line as u32,
-                end_line: location.line as u32,
-                start_column: Some(location.column as u32),
-                end_column: None,
-                message: format!(
-                    r#"features: [{}]
-
-cause:
-{}
-
-{}"#,
-                    features.join(", "),
-                    failure.panic_text.replace("\r\n", "\n").replace('\r', "\n"),
-                    failure.stacktrace.replace("\r\n", "\n").replace('\r', "\n")
-                ),
-                title: Some(result.name.clone()),
-                raw_details: Some(format!("{:#?}", result)),
-            })
-        }
-        for result in test_run
-            .doc_test_run
-            .test_results
-            .iter()
-            .filter(|t| matches!(t.result, TestResultValue::Failed(_)))
-        {
-            let failure = result.result.unwrap_failure_ref();
-            let location = &failure.location;
-
-            let (_, real_line, real_column) =
-                DOCTEST_NAME_FILE_REGEX.with(|r| -> miette::Result<(String, u64, u64)> {
-                    if let Some(c) = r.captures(&result.name) {
-                        parse_capture!(let file: String = c);
-                        parse_capture!(let line: u64 = c);
-
-                        let real_line = location.line + line - 3;
-                        let real_column = location.column + 4;
-                        return Ok((file, real_line, real_column));
-                    }
-                    miette::bail!("Doctest title in unexpected format: {}", &result.name);
-                })?;
-
-            annotations.push(CheckRunOutputAnnotation {
-                annotation_level: CheckRunOutputAnnotationLevel::Failure,
-                path: location.file.clone(),
-                start_line: real_line as u32,
-                end_line: real_line as u32,
-                start_column: Some(real_column as u32),
-                end_column: None,
-                message: format!(
-                    r#"features: [{}]
-    
-cause:
-{}
-
-{}"#,
-                    features.join(", "),
-                    failure.panic_text.replace("\r\n", "\n").replace('\r', "\n"),
-                    failure.stacktrace.replace("\r\n", "\n").replace('\r', "\n")
-                ),
-                title: Some(result.name.clone()),
-                raw_details: Some(format!("{:#?}", result)),
-            })
+    // GitHub only takes so many annotations per request, and adds those of
+    // later updates to the ones already there.
+    let mut batches: Vec<Vec<CheckRunOutputAnnotation>> = Vec::new();
+    for annotation in annotations {
+        match batches.last_mut() {
+            Some(batch) if batch.len() < MAX_ANNOTATIONS_PER_REQUEST => batch.push(annotation),
+            _ => batches.push(vec![annotation]),
         }
     }
-
-    let repo = std::env::var("GITHUB_REPOSITORY").expect("GITHUB_REPOSITORY env variable");
-    let mut repo_split = repo.split('/');
-    let owner = repo_split.next().expect("repo owner");
-    let repo = repo_split.next().expect("repo");
-    let sha = std::env::var("GITHUB_SHA").expect("GITHUB_SHA env variable");
+    let mut batches = batches.into_iter();
+    let output = |annotations| CheckRunOutput {
+        annotations,
+        title: name.clone(),
+        summary: summary.clone(),
+        text: None,
+        images: Vec::new(),
+    };
 
     let checks = octocrab.checks(owner, repo);
-    let annotations_count = annotations.len();
-    if annotations.is_empty() {
-        let output = CheckRunOutput {
-            annotations,
-            title: name.clone(),
-            summary: format!("{} test failures", annotations_count),
-            text: None,
-            images: Vec::new(),
-        };
-        let _check_run = checks
-            .create_check_run(name, sha)
-            .output(output)
-            .status(CheckRunStatus::Completed)
-            .conclusion(CheckRunConclusion::Success)
-            .completed_at(Utc::now())
-            .send()
-            .await
-            .into_diagnostic()?;
-    } else if annotations_count < 50 {
-        let output = CheckRunOutput {
-            annotations,
-            title: name.clone(),
-            summary: format!("{} test failures", annotations_count),
-            text: None,
-            images: Vec::new(),
-        };
-        let _check_run = checks
-            .create_check_run(name, sha)
-            .output(output)
-            .status(CheckRunStatus::Completed)
-            .conclusion(CheckRunConclusion::Failure)
-            .completed_at(Utc::now())
+    let check_run = checks
+        .create_check_run(name.clone(), sha)
+        .output(output(batches.next().unwrap_or_default()))
+        .status(CheckRunStatus::Completed)
+        .conclusion(if failed {
+            CheckRunConclusion::Failure
+        } else {
+            CheckRunConclusion::Success
+        })
+        .completed_at(Utc::now())
+        .send()
+        .await
+        .into_diagnostic()?;
+    for batch in batches {
+        checks
+            .update_check_run(check_run.id)
+            .output(output(batch))
             .send()
             .await
             .into_diagnostic()?;
-    } else {
-        todo!("report annotations in batches when > 50; API limitation")
     }
-    // TODO: Check the return value from the GitHub API for errors and such.
 
     Ok(())
 }
 
-thread_local! {
-    static DOCTEST_NAME_FILE_REGEX: Regex = Regex::new(r"(?P<file>.+?) - \(line (?P<line>\d+)\)").unwrap();
+/// The most annotations GitHub accepts in a single request.
+const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+
+/// Counts the failures and the warnings among `annotations`, which are told
+/// apart since warnings don't fail the check.
+fn annotations_summary(annotations: &[Annotation], total_time: Duration) -> String {
+    let count = |level| annotations.iter().filter(|a| a.level == level).count();
+    summary_line(
+        count(AnnotationLevel::Failure),
+        count(AnnotationLevel::Warning),
+        total_time,
+    )
+}
+
+fn summary_line(failures: usize, warnings: usize, total_time: Duration) -> String {
+    let mut summary = format!("{} test failures", failures);
+    if warnings > 0 {
+        summary += &format!(", {} warnings", warnings);
+    }
+    summary + &format!("; tests ran for {:.2}s", total_time.as_secs_f64())
+}
+
+/// An environment variable set by GitHub Actions.
+fn github_env(name: &str) -> miette::Result<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| {
+            miette::miette!(
+                help = "the `check-run` output only works in GitHub Actions; use `report` locally",
+                "{} is not set",
+                name
+            )
+        })
 }
@@ -0,0 +1,158 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Mapping of the paths found in test output to paths relative to the root of
+//! the repository, which is what GitHub expects.
+
+use cargo_metadata::{Metadata, Package};
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Clone, Debug)]
+pub struct RepoPaths {
+    repo_root: String,
+    workspace_root: String,
+    /// The workspace root relative to the repository root, or the empty string
+    /// if they're one and the same.
+    workspace_prefix: String,
+}
+
+impl RepoPaths {
+    /// Uses the given path of the workspace root relative to the repository
+    /// root.
+    pub fn with_path_prefix(metadata: &Metadata, path_prefix: &str) -> Self {
+        let workspace_prefix = normalize(path_prefix).unwrap_or_default();
+        let workspace_root = metadata.workspace_root.as_str().replace('\\', "/");
+        let mut repo_root = workspace_root.trim_end_matches('/');
+        for _ in workspace_prefix.split('/').filter(|c| !c.is_empty()) {
+            repo_root = repo_root.rsplit_once('/').map_or("", |(parent, _)| parent);
+        }
+
+        Self {
+            repo_root: repo_root.to_owned(),
+            workspace_root,
+            workspace_prefix,
+        }
+    }
+
+    /// Asks git where the repository root is. If that fails, the workspace root
+    /// is assumed to be the repository root.
+    pub fn discover(metadata: &Metadata) -> Self {
+        let workspace_root = metadata.workspace_root.as_str().replace('\\', "/");
+        let repo_root = Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(&metadata.workspace_root)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+            .map(|r| r.trim().replace('\\', "/"));
+        let (repo_root, workspace_prefix) = repo_root
+            .and_then(|r| {
+                let workspace_prefix = strip_path_prefix(&workspace_root, &r)?.to_owned();
+                Some((r, workspace_prefix))
+            })
+            .unwrap_or_else(|| (workspace_root.clone(), String::new()));
+
+        Self {
+            repo_root,
+            workspace_root,
+            workspace_prefix,
+        }
+    }
+
+    /// The absolute path of the repository root.
+    pub fn repo_root(&self) -> &str {
+        &self.repo_root
+    }
+
+    /// Resolves `file`, as found in the output of `package`'s tests, to a path
+    /// relative to the repository root. Returns `None` if the file is outside
+    /// the repository, like the standard library or a dependency in the cargo
+    /// registry.
+    pub fn resolve(&self, package: &Package, file: &str) -> Option<String> {
+        let file = file.replace('\\', "/");
+        let workspace_relative = if is_absolute(&file) {
+            match strip_path_prefix(&file, &self.workspace_root) {
+                Some(workspace_relative) => workspace_relative.to_owned(),
+                // Files elsewhere in the repository, e.g. a path dependency
+                // next to the workspace.
+                None => return normalize(strip_path_prefix(&file, &self.repo_root)?),
+            }
+        } else {
+            // rustc is invoked from the workspace root, so paths are usually
+            // relative to it, but packages outside the workspace directory (or
+            // tests run from the package directory) produce paths relative to
+            // the package instead.
+            let package_root = package
+                .manifest_path
+                .parent()
+                .map(|p| p.as_str().replace('\\', "/"))
+                .unwrap_or_default();
+            let exists = |root: &str| Path::new(root).join(&file).exists();
+            if !exists(&self.workspace_root) && exists(&package_root) {
+                match strip_path_prefix(&package_root, &self.workspace_root) {
+                    Some(package_prefix) => format!("{}/{}", package_prefix, file),
+                    None => {
+                        let package_prefix = strip_path_prefix(&package_root, &self.repo_root)?;
+                        return normalize(&format!("{}/{}", package_prefix, file));
+                    }
+                }
+            } else {
+                file
+            }
+        };
+
+        normalize(&format!("{}/{}", self.workspace_prefix, workspace_relative))
+    }
+}
+
+fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+/// Strips `prefix` off of `path` if `path` is inside of it, returning the
+/// remainder without a leading `/`.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let prefix = prefix.trim_end_matches('/');
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
+    }
+}
+
+/// Resolves `.` and `..` components and removes empty ones. Returns `None` if
+/// the path climbs out of its root.
+fn normalize(path: &str) -> Option<String> {
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            c => components.push(c),
+        }
+    }
+
+    Some(components.join("/"))
+}
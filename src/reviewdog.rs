@@ -0,0 +1,162 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test failures in the formats reviewdog reads: its own
+//! `rdjsonl` and Checkstyle XML.
+
+use crate::annotations::{distinct_failures, AnnotatedFailure, Annotation, AnnotationLevel};
+use crate::junit::escape_attribute;
+use crate::TestRun;
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use std::io::Write;
+
+/// The name test failures are reported under, to tell them apart from the
+/// diagnostics of other tools.
+pub const SOURCE_NAME: &str = "cargo-test";
+
+/// Writes a reviewdog diagnostic for every annotation of a failure in
+/// `test_runs`, one JSON object per line.
+pub fn write_rdjsonl<W: Write>(
+    mut w: W,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+) -> miette::Result<()> {
+    for diagnostic in diagnostics(test_runs, annotations) {
+        serde_json::to_writer(&mut w, &diagnostic).into_diagnostic()?;
+        writeln!(w).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Writes a Checkstyle report with an error for every annotation of a failure
+/// in `test_runs`.
+pub fn write_checkstyle<W: Write>(
+    mut w: W,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+) -> std::io::Result<()> {
+    let mut files: Vec<(String, Vec<Diagnostic>)> = Vec::new();
+    for diagnostic in diagnostics(test_runs, annotations) {
+        let path = &diagnostic.location.path;
+        match files.iter_mut().find(|(p, _)| p == path) {
+            Some((_, diagnostics)) => diagnostics.push(diagnostic),
+            None => files.push((path.clone(), vec![diagnostic])),
+        }
+    }
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<checkstyle version="4.3">"#)?;
+    for (path, diagnostics) in files {
+        writeln!(w, r#"  <file name="{}">"#, escape_attribute(&path))?;
+        for diagnostic in diagnostics {
+            let start = &diagnostic.location.range.start;
+            write!(w, r#"    <error line="{}""#, start.line)?;
+            if let Some(column) = start.column {
+                write!(w, r#" column="{}""#, column)?;
+            }
+            writeln!(
+                w,
+                r#" severity="{}" message="{}" source="{}.{}"/>"#,
+                match diagnostic.severity {
+                    "ERROR" => "error",
+                    "WARNING" => "warning",
+                    _ => "info",
+                },
+                escape_attribute(&diagnostic.message),
+                SOURCE_NAME,
+                diagnostic.code.value
+            )?;
+        }
+        writeln!(w, "  </file>")?;
+    }
+    writeln!(w, "</checkstyle>")
+}
+
+fn diagnostics<'a>(
+    test_runs: &'a [TestRun],
+    annotations: &'a [Annotation],
+) -> impl Iterator<Item = Diagnostic> + 'a {
+    distinct_failures(test_runs, annotations).filter_map(
+        |AnnotatedFailure {
+             result,
+             failure,
+             kind,
+             annotation,
+             ..
+         }| {
+            let annotation = annotation?;
+            Some(Diagnostic {
+                message: format!("{} failed: {}", result.name, failure.panic_text),
+                location: Location {
+                    path: annotation.target.path.clone(),
+                    range: Range {
+                        start: Position {
+                            line: annotation.target.line,
+                            column: annotation.target.column,
+                        },
+                    },
+                },
+                severity: match annotation.level {
+                    AnnotationLevel::Failure => "ERROR",
+                    AnnotationLevel::Warning => "WARNING",
+                    AnnotationLevel::Notice => "INFO",
+                },
+                source: Source { name: SOURCE_NAME },
+                code: Code { value: kind.id() },
+            })
+        },
+    )
+}
+
+#[derive(Serialize)]
+struct Diagnostic {
+    message: String,
+    location: Location,
+    severity: &'static str,
+    source: Source,
+    code: Code,
+}
+
+#[derive(Serialize)]
+struct Location {
+    path: String,
+    range: Range,
+}
+
+#[derive(Serialize)]
+struct Range {
+    start: Position,
+}
+
+#[derive(Serialize)]
+struct Position {
+    line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Source {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+struct Code {
+    value: &'static str,
+}
@@ -0,0 +1,172 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Running `cargo test` ourselves, while still showing its output as it goes.
+
+use crate::TestRun;
+use cargo_metadata::{Message, Metadata};
+use miette::{Context, IntoDiagnostic};
+use regex::Regex;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, ExitStatus};
+
+/// Runs `cargo test --message-format json` with `args`, echoing what it would
+/// have printed without `--message-format json` to the console, and parses its
+/// output as it streams in.
+///
+/// Cargo's stderr is read through the same pipe as its stdout, so that the
+/// `Running` headers arrive in order with the output of each test binary. The
+/// exit status is returned even if the output couldn't be parsed.
+pub fn cargo_test(
+    args: &[OsString],
+    metadata: Metadata,
+) -> miette::Result<(miette::Result<Vec<TestRun>>, ExitStatus)> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let (reader, writer) = std::io::pipe().into_diagnostic()?;
+    let mut command = Command::new(cargo);
+    command
+        .args(["test", "--message-format", "json"])
+        .args(args)
+        .stdout(writer.try_clone().into_diagnostic()?)
+        .stderr(writer);
+    let mut child = command
+        .spawn()
+        .into_diagnostic()
+        .wrap_err("could not run `cargo test`")?;
+    // Otherwise the pipe stays open after cargo exits.
+    drop(command);
+
+    let mut echo = Echo::new(BufReader::new(reader), std::io::stdout(), std::io::stderr());
+    let test_runs = crate::parse(&mut echo, metadata);
+    if test_runs.is_err() {
+        // Keep showing the output of the tests even if we can't make sense of
+        // it.
+        std::io::copy(&mut echo, &mut std::io::sink()).into_diagnostic()?;
+    }
+    let status = child.wait().into_diagnostic()?;
+
+    Ok((test_runs, status))
+}
+
+/// Finds the value of `--manifest-path` in the arguments to `cargo test`.
+pub fn manifest_path(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter().take_while(|a| *a != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--manifest-path" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg
+            .to_str()
+            .and_then(|a| a.strip_prefix("--manifest-path="))
+        {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// Passes on the output of `cargo test --message-format json` unchanged, while
+/// writing the output of the tests to `out` and cargo's status lines and the
+/// rendered compiler messages to `err`, like `cargo test` normally does.
+pub struct Echo<R, O, E> {
+    inner: R,
+    out: O,
+    err: E,
+    line: Vec<u8>,
+    position: usize,
+}
+
+impl<R: BufRead, O: Write, E: Write> Echo<R, O, E> {
+    pub fn new(inner: R, out: O, err: E) -> Self {
+        Self {
+            inner,
+            out,
+            err,
+            line: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn echo_line(&mut self) -> std::io::Result<()> {
+        let line = String::from_utf8_lossy(&self.line);
+        let text = line.trim_end_matches(['\r', '\n']);
+        if text.starts_with('{') {
+            match serde_json::from_str::<Message>(text) {
+                Ok(Message::CompilerMessage(message)) => {
+                    if let Some(rendered) = &message.message.rendered {
+                        self.err.write_all(rendered.as_bytes())?;
+                        self.err.flush()?;
+                    }
+                    return Ok(());
+                }
+                Ok(_) => return Ok(()),
+                Err(_) => {
+                    // Messages we don't know about are still cargo's.
+                    let value = serde_json::from_str::<serde_json::Value>(text);
+                    if value.is_ok_and(|v| v.get("reason").is_some()) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if is_cargo_status(text) {
+            self.err.write_all(&self.line)?;
+            self.err.flush()
+        } else {
+            self.out.write_all(&self.line)?;
+            self.out.flush()
+        }
+    }
+}
+
+impl<R: BufRead, O: Write, E: Write> Read for Echo<R, O, E> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.line.len() {
+            self.line.clear();
+            self.position = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            self.echo_line()?;
+        }
+
+        let remaining = &self.line[self.position..];
+        let count = remaining.len().min(buf.len());
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Whether `text` is a line cargo printed to stderr, like `     Running …`,
+/// `error: test failed, …` or one of the targets listed after `error: 2 targets
+/// failed:`.
+fn is_cargo_status(text: &str) -> bool {
+    CARGO_STATUS_REGEX.with(|r| {
+        r.captures(text).is_some_and(|c| {
+            // Cargo right-aligns the verb of its status lines to a width of 12.
+            c.name("status").is_none_or(|s| s.len() == 12)
+        })
+    })
+}
+
+thread_local! {
+    static CARGO_STATUS_REGEX: Regex = Regex::new(r"^(?:(?P<status> *[A-Z][a-z]+(?:-[a-z]+)?) |error: | {4}`--[^`]*`$)").unwrap();
+}
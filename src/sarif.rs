@@ -0,0 +1,323 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test failures as SARIF 2.1.0, for GitHub code scanning and
+//! other SARIF viewers.
+
+use crate::annotations::{distinct_failures, AnnotatedFailure, Annotation, AnnotationLevel};
+use crate::paths::RepoPaths;
+use crate::{FailureKind, TestFailureInfo, TestResult, TestRun};
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use std::io::Write;
+
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+pub const SARIF_VERSION: &str = "2.1.0";
+
+/// The base that all artifact locations are relative to, which is the root of
+/// the repository.
+const SOURCE_ROOT: &str = "%SRCROOT%";
+
+/// Writes a SARIF log with a result for every failed test in `test_runs`,
+/// located where the test was annotated. A test that failed the same way with
+/// several sets of features has a single result. Backtrace frames inside the
+/// repository become a code flow.
+pub fn write<W: Write>(
+    w: W,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+    repo_paths: &RepoPaths,
+) -> miette::Result<()> {
+    let rules = FailureKind::ALL
+        .iter()
+        .map(|kind| ReportingDescriptor {
+            id: kind.id(),
+            short_description: Message {
+                text: kind.description().to_owned(),
+            },
+            default_configuration: ReportingConfiguration { level: "error" },
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for AnnotatedFailure {
+        test_run,
+        result,
+        failure,
+        kind,
+        annotation,
+        ..
+    } in distinct_failures(test_runs, annotations)
+    {
+        results.push(SarifResult {
+            rule_id: kind.id(),
+            rule_index: FailureKind::ALL
+                .iter()
+                .position(|k| *k == kind)
+                .expect("all failure kinds have a rule"),
+            level: match annotation.map(|a| a.level) {
+                None | Some(AnnotationLevel::Failure) => "error",
+                Some(AnnotationLevel::Warning) => "warning",
+                Some(AnnotationLevel::Notice) => "note",
+            },
+            message: Message {
+                text: format!("{} failed: {}", result.name, failure.panic_text),
+            },
+            locations: match annotation {
+                Some(a) => vec![Location {
+                    physical_location: PhysicalLocation::new(
+                        &a.target.path,
+                        a.target.line,
+                        a.target.column,
+                    ),
+                    message: None,
+                }],
+                None => target_file(test_run, result, repo_paths)
+                    .map(|path| Location {
+                        physical_location: PhysicalLocation::new(&path, 1, None),
+                        message: None,
+                    })
+                    .into_iter()
+                    .collect(),
+            },
+            code_flows: code_flows(test_run, failure, repo_paths),
+            properties: ResultProperties {
+                package: test_run.package.name.to_string(),
+                feature_sets: annotation
+                    .map(|a| a.feature_sets.clone())
+                    .unwrap_or_else(|| vec![test_run.features.clone()]),
+                test: result.name.clone(),
+                flaky: annotation.is_some_and(|a| a.flaky),
+            },
+        });
+    }
+
+    let log = SarifLog {
+        schema: SARIF_SCHEMA,
+        version: SARIF_VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: ToolComponent {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    information_uri: env!("CARGO_PKG_REPOSITORY"),
+                    rules,
+                },
+            },
+            results,
+            column_kind: "unicodeCodePoints",
+        }],
+    };
+    serde_json::to_writer_pretty(w, &log).into_diagnostic()
+}
+
+/// The source file of the target whose test binary ran `result`, or of the
+/// package's library if that isn't known, for failures that weren't annotated.
+fn target_file(test_run: &TestRun, result: &TestResult, repo_paths: &RepoPaths) -> Option<String> {
+    let targets = &test_run.package.targets;
+    let target = match &result.target {
+        Some(name) => targets.iter().find(|t| t.name == *name),
+        None => targets.iter().find(|t| t.is_lib()),
+    }
+    .or_else(|| targets.first())?;
+    repo_paths.resolve(&test_run.package, target.src_path.as_str())
+}
+
+/// The frames of the backtrace of `failure` that are inside the repository,
+/// outermost first, since that's the order they executed in.
+fn code_flows(
+    test_run: &TestRun,
+    failure: &TestFailureInfo,
+    repo_paths: &RepoPaths,
+) -> Vec<CodeFlow> {
+    let locations: Vec<_> = failure
+        .backtrace_frames()
+        .into_iter()
+        .rev()
+        .filter_map(|frame| {
+            let location = frame.location?;
+            let path = repo_paths.resolve(&test_run.package, &location.file)?;
+            Some(ThreadFlowLocation {
+                location: Location {
+                    physical_location: PhysicalLocation::new(
+                        &path,
+                        location.line,
+                        Some(location.column),
+                    ),
+                    message: Some(Message {
+                        text: frame.function,
+                    }),
+                },
+            })
+        })
+        .collect();
+    if locations.is_empty() {
+        return Vec::new();
+    }
+
+    vec![CodeFlow {
+        thread_flows: vec![ThreadFlow { locations }],
+    }]
+}
+
+/// Percent-encodes `path` for use as a relative URI reference.
+fn encode_uri_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[derive(Serialize)]
+struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+    column_kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: ToolComponent,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolComponent {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportingDescriptor {
+    id: &'static str,
+    short_description: Message,
+    default_configuration: ReportingConfiguration,
+}
+
+#[derive(Serialize)]
+struct ReportingConfiguration {
+    level: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    code_flows: Vec<CodeFlow>,
+    properties: ResultProperties,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResultProperties {
+    package: String,
+    feature_sets: Vec<Vec<String>>,
+    test: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    flaky: bool,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<Message>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+impl PhysicalLocation {
+    fn new(path: &str, line: u64, column: Option<u64>) -> Self {
+        Self {
+            artifact_location: ArtifactLocation {
+                uri: encode_uri_path(path),
+                uri_base_id: SOURCE_ROOT,
+            },
+            region: Region {
+                start_line: line.max(1),
+                start_column: column.map(|c| c.max(1)),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation {
+    uri: String,
+    uri_base_id: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_column: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeFlow {
+    thread_flows: Vec<ThreadFlow>,
+}
+
+#[derive(Serialize)]
+struct ThreadFlow {
+    locations: Vec<ThreadFlowLocation>,
+}
+
+#[derive(Serialize)]
+struct ThreadFlowLocation {
+    location: Location,
+}
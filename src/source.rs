@@ -0,0 +1,383 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Checks of annotation targets against the checked-out source, since GitHub
+//! silently drops annotations on files that don't exist or lines beyond the end
+//! of a file.
+
+use crate::paths::RepoPaths;
+use cargo_metadata::{Package, Target, TargetKind};
+use regex::Regex;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A place in a file in the repository to put an annotation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AnnotationTarget {
+    /// The path of the file relative to the repository root.
+    pub path: String,
+    pub line: u64,
+    pub column: Option<u64>,
+}
+
+impl AnnotationTarget {
+    pub fn new(path: String, line: u64, column: Option<u64>) -> Self {
+        Self { path, line, column }
+    }
+}
+
+impl fmt::Display for AnnotationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        Ok(())
+    }
+}
+
+/// How an annotation target was arrived at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Where the test output said the test failed.
+    AsReported,
+    /// The definition of the test function.
+    TestFunction,
+    /// The first line of the source file of the test target.
+    TargetFile,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AsReported => "reported location",
+            Self::TestFunction => "test function definition",
+            Self::TargetFile => "test target source file",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceTree<'a> {
+    repo_paths: &'a RepoPaths,
+}
+
+impl<'a> SourceTree<'a> {
+    pub fn new(repo_paths: &'a RepoPaths) -> Self {
+        Self { repo_paths }
+    }
+
+    pub fn repo_paths(&self) -> &'a RepoPaths {
+        self.repo_paths
+    }
+
+    /// Finds a place to annotate the failure of the test `test_name` of
+    /// `package`, preferring the `reported` target if it points at an existing
+    /// line. Otherwise, falls back to the definition of the test function
+    /// (except for doctests, which have no function), and finally to the
+    /// source file of the test's `target`.
+    pub fn place(
+        &self,
+        package: &Package,
+        target: Option<&str>,
+        test_name: &str,
+        is_doctest: bool,
+        reported: Option<AnnotationTarget>,
+    ) -> Option<(AnnotationTarget, Placement)> {
+        if let Some(reported) = reported.filter(|t| self.contains(t)) {
+            return Some((reported, Placement::AsReported));
+        }
+        if !is_doctest {
+            if let Some(target) = self.find_test_function(package, test_name) {
+                return Some((target, Placement::TestFunction));
+            }
+        }
+        self.find_target_file(package, target, is_doctest)
+            .map(|target| (target, Placement::TargetFile))
+    }
+
+    /// Whether the file of `target` exists and has the target's line.
+    pub fn contains(&self, target: &AnnotationTarget) -> bool {
+        let Ok(source) = std::fs::read_to_string(self.absolute(&target.path)) else {
+            return false;
+        };
+        target.line >= 1 && target.line <= source.lines().count().max(1) as u64
+    }
+
+    /// The lines of source around `target`, with `context` lines on either
+    /// side, numbered and with the target line marked like in rustc's
+    /// diagnostics. For doctests, these are the lines of the doc comment or
+    /// Markdown file.
+    pub fn snippet(&self, target: &AnnotationTarget, context: u64) -> Option<String> {
+        let source = std::fs::read_to_string(self.absolute(&target.path)).ok()?;
+        let lines: Vec<_> = source.lines().collect();
+        let line = usize::try_from(target.line).ok()?;
+        if line == 0 || line > lines.len() {
+            return None;
+        }
+        let first = line.saturating_sub(context as usize).max(1);
+        let last = (line + context as usize).min(lines.len());
+        let width = last.to_string().len();
+
+        let mut snippet = String::new();
+        for number in first..=last {
+            let text = lines[number - 1];
+            let marker = if number == line { '>' } else { ' ' };
+            snippet += format!("{} {:>width$} | {}", marker, number, text).trim_end();
+            snippet.push('\n');
+            if let Some(column) = target.column.filter(|_| number == line) {
+                // Keep tabs, so the caret lines up with the column.
+                let indent: String = text
+                    .chars()
+                    .take(column.saturating_sub(1) as usize)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                snippet += &format!("  {:width$} | {}^\n", "", indent);
+            }
+        }
+        snippet.pop();
+        Some(snippet)
+    }
+
+    /// Looks for the definition of the test function of `test_name` in the
+    /// source files of `package`, preferring files named after the modules in
+    /// the test's path.
+    pub fn find_test_function(
+        &self,
+        package: &Package,
+        test_name: &str,
+    ) -> Option<AnnotationTarget> {
+        let mut segments: Vec<_> = test_name.split("::").collect();
+        let function = segments.pop()?;
+        let function_regex = Regex::new(&format!(
+            r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:async\s+)?fn\s+{}\s*[(<]",
+            regex::escape(function)
+        ))
+        .ok()?;
+
+        let package_root = package.manifest_path.parent()?.as_std_path();
+        let mut files = Vec::new();
+        find_source_files(package_root, &mut files);
+
+        let mut best: Option<(usize, AnnotationTarget)> = None;
+        for file in files {
+            let Ok(source) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            let Some(line) = source.lines().position(|l| function_regex.is_match(l)) else {
+                continue;
+            };
+            let Some(path) = self.repo_paths.resolve(package, &file.to_string_lossy()) else {
+                continue;
+            };
+
+            let score = segments
+                .iter()
+                .filter(|s| {
+                    path.split('/')
+                        .any(|c| c == **s || c.strip_suffix(".rs") == Some(**s))
+                })
+                .count();
+            if best
+                .as_ref()
+                .is_none_or(|(best_score, _)| score > *best_score)
+            {
+                best = Some((score, AnnotationTarget::new(path, line as u64 + 1, None)));
+            }
+        }
+
+        best.map(|(_, target)| target)
+    }
+
+    /// The source file of the target named `target`, whose tests (or
+    /// doctests) failed. If the target isn't known, as in test results from
+    /// before they said, this prefers the library target.
+    pub fn find_target_file(
+        &self,
+        package: &Package,
+        target: Option<&str>,
+        is_doctest: bool,
+    ) -> Option<AnnotationTarget> {
+        let is_lib = |t: &&Target| {
+            t.kind.iter().any(|k| {
+                matches!(
+                    k,
+                    TargetKind::Lib | TargetKind::RLib | TargetKind::ProcMacro
+                )
+            })
+        };
+        let is_tested = |t: &&Target| if is_doctest { t.doctest } else { t.test };
+        let named = target.and_then(|name| {
+            package
+                .targets
+                .iter()
+                .filter(is_tested)
+                .find(|t| t.name == name)
+        });
+        let target = named
+            .or_else(|| package.targets.iter().filter(is_tested).find(is_lib))
+            .or_else(|| package.targets.iter().find(is_tested))?;
+        let path = self.repo_paths.resolve(package, target.src_path.as_str())?;
+
+        let target = AnnotationTarget::new(path, 1, None);
+        self.contains(&target).then_some(target)
+    }
+
+    /// Reads the code of the doctest whose code block opens on `line` of the
+    /// file at `path`.
+    pub fn doctest(&self, path: &str, line: u64) -> Option<Doctest> {
+        let source = std::fs::read_to_string(self.absolute(path)).ok()?;
+        let mut lines = source
+            .lines()
+            .zip(1..)
+            .skip(usize::try_from(line).ok()?.checked_sub(1)?);
+
+        let (fence_line, _) = lines.next()?;
+        let (comment, indent, fence) = FENCE_REGEX.with(|r| {
+            let c = r.captures(fence_line)?;
+            Some((
+                c["comment"].to_owned(),
+                c["indent"].len(),
+                c["fence"].to_owned(),
+            ))
+        })?;
+
+        let mut code = Vec::new();
+        for (source_line, number) in lines {
+            let Some(text) = source_line.trim_start().strip_prefix(comment.trim_start()) else {
+                break;
+            };
+            let stripped = text.len() - text.trim_start().len();
+            let text = &text[stripped.min(indent)..];
+            if text.trim_start().starts_with(&fence) {
+                return Some(Doctest { lines: code });
+            }
+            // Hidden lines are compiled without their `# `.
+            let trimmed = text.trim_start();
+            let compiled = match trimmed.strip_prefix("# ") {
+                Some(rest) => rest,
+                None if trimmed == "#" => "",
+                None => text,
+            };
+            code.push(DoctestLine {
+                number,
+                offset: (source_line.len() - compiled.len()) as u64,
+                code: compiled.to_owned(),
+            });
+        }
+        None
+    }
+
+    fn absolute(&self, path: &str) -> PathBuf {
+        Path::new(self.repo_paths.repo_root()).join(path)
+    }
+}
+
+/// The code of a doctest, as written in the doc comment or Markdown file it's
+/// in.
+#[derive(Clone, Debug)]
+pub struct Doctest {
+    lines: Vec<DoctestLine>,
+}
+
+#[derive(Clone, Debug)]
+struct DoctestLine {
+    /// The number of the line in the file.
+    number: u64,
+    /// How many bytes precede the code on the line, like `/// `.
+    offset: u64,
+    code: String,
+}
+
+impl Doctest {
+    /// Where `line` and `column` of the program rustdoc runs the doctest as,
+    /// when it's not merged with others, are in the file. The program starts
+    /// with `#![allow(unused)]`, the crate attributes of the doctest and an
+    /// empty line, its `extern crate`s, an `extern crate` of `crate_name` if
+    /// the doctest uses it, and, unless the doctest has a `main` of its own,
+    /// the line opening the `main` rustdoc wraps the rest in.
+    pub fn locate_standalone(
+        &self,
+        crate_name: &str,
+        line: u64,
+        column: u64,
+    ) -> Option<(u64, u64)> {
+        let is_crate_attribute = |l: &&DoctestLine| l.code.trim_start().starts_with("#![");
+        let is_extern_crate = |l: &&DoctestLine| l.code.trim_start().starts_with("extern crate ");
+
+        let crate_attributes = self.lines.iter().filter(is_crate_attribute).count();
+        let extern_crates = self.lines.iter().filter(is_extern_crate).count();
+        let uses_crate = self.lines.iter().any(|l| l.code.contains(crate_name));
+        let has_main = self
+            .lines
+            .iter()
+            .any(|l| MAIN_REGEX.with(|r| r.is_match(&l.code)));
+
+        let mut prelude = 1 + extern_crates;
+        if crate_attributes > 0 {
+            prelude += crate_attributes + 1;
+        }
+        if uses_crate && extern_crates == 0 {
+            prelude += 2;
+        }
+        if !has_main {
+            prelude += 1;
+        }
+
+        let body_line = usize::try_from(line).ok()?.checked_sub(prelude + 1)?;
+        let line = self
+            .lines
+            .iter()
+            .filter(|l| !is_crate_attribute(l) && !is_extern_crate(l))
+            .nth(body_line)?;
+        Some((line.number, line.offset + column))
+    }
+
+    /// Where `column` of `line` of the file, as reported in an error compiling
+    /// the doctest, is. rustdoc reports the lines, but not the columns, as
+    /// they are in the file.
+    pub fn locate_compiled(&self, line: u64, column: u64) -> Option<(u64, u64)> {
+        let line = self.lines.iter().find(|l| l.number == line)?;
+        Some((line.number, line.offset + column))
+    }
+}
+
+fn find_source_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).map(|e| e.path()).collect();
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if entry.is_dir() {
+            find_source_files(&entry, files);
+        } else if name.ends_with(".rs") {
+            files.push(entry);
+        }
+    }
+}
+
+thread_local! {
+    static FENCE_REGEX: Regex = Regex::new(r"^(?P<comment>\s*(?://[/!])?)(?P<indent>\s*)(?P<fence>```+|~~~+)").unwrap();
+    static MAIN_REGEX: Regex = Regex::new(r"^\s*(?:pub\s+)?(?:async\s+)?fn\s+main\s*\(").unwrap();
+}
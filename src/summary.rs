@@ -0,0 +1,274 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test runs as a Markdown job summary, for
+//! `$GITHUB_STEP_SUMMARY`.
+
+use crate::annotations::{annotated_failures, AnnotatedFailure, Annotation};
+use crate::baseline::{Baseline, FailureStatus};
+use crate::flaky::flaky_tests;
+use crate::source::AnnotationTarget;
+use crate::{TestRun, TestSummary};
+use std::io::Write;
+use std::time::Duration;
+
+/// Links to lines of files in the repository at a specific commit.
+#[derive(Clone, Debug)]
+pub struct BlobLinks {
+    base: String,
+}
+
+impl BlobLinks {
+    pub fn new(server_url: &str, repository: &str, sha: &str) -> Self {
+        Self {
+            base: format!(
+                "{}/{}/blob/{}",
+                server_url.trim_end_matches('/'),
+                repository,
+                sha
+            ),
+        }
+    }
+
+    /// Uses the `GITHUB_SERVER_URL`, `GITHUB_REPOSITORY` and `GITHUB_SHA`
+    /// environment variables set by GitHub Actions.
+    pub fn from_env() -> Option<Self> {
+        let server_url =
+            std::env::var("GITHUB_SERVER_URL").unwrap_or_else(|_| "https://github.com".to_owned());
+        let repository = std::env::var("GITHUB_REPOSITORY").ok()?;
+        let sha = std::env::var("GITHUB_SHA").ok()?;
+
+        Some(Self::new(&server_url, &repository, &sha))
+    }
+
+    pub fn link(&self, target: &AnnotationTarget) -> String {
+        format!("{}/{}#L{}", self.base, target.path, target.line)
+    }
+}
+
+/// Writes a summary of `test_runs`: a table of the results of each package,
+/// followed by the details of each failure, linking to where it was annotated
+/// if `links` is given. Failures are split into new and old ones, and fixed
+/// tests are listed, if there's a `baseline` to compare with.
+pub fn write<W: Write>(
+    mut w: W,
+    title: &str,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+    links: Option<&BlobLinks>,
+    baseline: Option<&Baseline>,
+) -> std::io::Result<()> {
+    let mut packages: Vec<(&str, TestSummary)> = Vec::new();
+    for test_run in test_runs {
+        let name = test_run.package.name.as_str();
+        let index = match packages.iter().position(|(p, _)| *p == name) {
+            Some(index) => index,
+            None => {
+                packages.push((name, TestSummary::default()));
+                packages.len() - 1
+            }
+        };
+        let totals = &mut packages[index].1;
+        for summary in [
+            &test_run.test_run.test_summary,
+            &test_run.doc_test_run.test_summary,
+        ] {
+            totals.passed += summary.passed;
+            totals.failed += summary.failed;
+            totals.ignored += summary.ignored;
+            totals.filtered += summary.filtered;
+            totals.time += summary.total_time();
+        }
+    }
+
+    let failed: usize = packages.iter().map(|(_, s)| s.failed).sum();
+    let total_time: Duration = packages.iter().map(|(_, s)| s.time).sum();
+    writeln!(w, "## {}", escape_html(title))?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "{} {} test failures; tests ran for {:.2}s",
+        if failed == 0 { "✅" } else { "❌" },
+        failed,
+        total_time.as_secs_f64()
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "| Package | Passed | Failed | Ignored | Filtered | Time |"
+    )?;
+    writeln!(
+        w,
+        "| :------ | -----: | -----: | ------: | -------: | ---: |"
+    )?;
+    for (name, summary) in &packages {
+        writeln!(
+            w,
+            "| {} | {} | {} | {} | {} | {:.2}s |",
+            name,
+            summary.passed,
+            summary.failed,
+            summary.ignored,
+            summary.filtered,
+            summary.time.as_secs_f64()
+        )?;
+    }
+
+    let flaky_tests = flaky_tests(test_runs);
+    if !flaky_tests.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "### Flaky tests")?;
+        writeln!(w)?;
+        writeln!(w, "| Test | Package | Features | Passed | Failed |")?;
+        writeln!(w, "| :--- | :------ | :------- | -----: | -----: |")?;
+        for test in flaky_tests.iter() {
+            writeln!(
+                w,
+                "| <code>{}</code> | {} | {} | {} | {} |",
+                escape_html(&test.name),
+                test.package,
+                escape_html(&test.features.join(", ")),
+                test.passed,
+                test.failed
+            )?;
+        }
+    }
+
+    let failures: Vec<_> = annotated_failures(test_runs, annotations).collect();
+    match baseline {
+        None => write_failures(&mut w, "Failures", &failures, links)?,
+        Some(baseline) => {
+            let (new, still_failing): (Vec<_>, Vec<_>) = failures.iter().partition(|f| {
+                baseline.status(
+                    &f.test_run.package.name,
+                    &f.test_run.features,
+                    &f.result.name,
+                ) == FailureStatus::New
+            });
+            write_failures(&mut w, "New failures", &new, links)?;
+            write_failures(&mut w, "Still failing", &still_failing, links)?;
+
+            let fixed = baseline.fixed(test_runs);
+            if !fixed.is_empty() {
+                writeln!(w)?;
+                writeln!(w, "### Fixed")?;
+                writeln!(w)?;
+                for test in fixed {
+                    write!(
+                        w,
+                        "- <code>{}</code> in <code>{}</code>",
+                        escape_html(&test.name),
+                        escape_html(&test.package)
+                    )?;
+                    write_features(&mut w, &test.features)?;
+                    writeln!(w)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the details of each of `failures` under a `title` heading, if there
+/// are any.
+fn write_failures<W: Write>(
+    w: &mut W,
+    title: &str,
+    failures: &[AnnotatedFailure],
+    links: Option<&BlobLinks>,
+) -> std::io::Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    writeln!(w)?;
+    writeln!(w, "### {}", title)?;
+    for AnnotatedFailure {
+        test_run,
+        result,
+        failure,
+        annotation,
+        ..
+    } in failures
+    {
+        writeln!(w)?;
+        writeln!(w, "<details>")?;
+        write!(
+            w,
+            "<summary><code>{}</code> in <code>{}</code>",
+            escape_html(&result.name),
+            escape_html(&test_run.package.name)
+        )?;
+        write_features(w, &test_run.features)?;
+        writeln!(w, "</summary>")?;
+        writeln!(w)?;
+        if let Some(annotation) = annotation {
+            match links {
+                Some(links) => writeln!(
+                    w,
+                    "At [{}]({})",
+                    annotation.target,
+                    links.link(&annotation.target)
+                )?,
+                None => writeln!(w, "At `{}`", annotation.target)?,
+            }
+            writeln!(w)?;
+        }
+        write_code_block(w, &failure.panic_text)?;
+        if !failure.stacktrace.is_empty() {
+            writeln!(w)?;
+            write_code_block(w, &failure.stacktrace)?;
+        }
+        writeln!(w)?;
+        writeln!(w, "</details>")?;
+    }
+
+    Ok(())
+}
+
+fn write_features<W: Write>(w: &mut W, features: &[String]) -> std::io::Result<()> {
+    if features.is_empty() {
+        return Ok(());
+    }
+    write!(
+        w,
+        " with features <code>{}</code>",
+        escape_html(&features.join(", "))
+    )
+}
+
+/// Writes `text` as a fenced code block, with a fence longer than any run of
+/// backticks in it.
+fn write_code_block<W: Write>(w: &mut W, text: &str) -> std::io::Result<()> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let longest_run = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    writeln!(w, "{}text", fence)?;
+    writeln!(w, "{}", text.trim_end())?;
+    writeln!(w, "{}", fence)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
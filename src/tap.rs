@@ -0,0 +1,286 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Rendering of test runs as TAP (Test Anything Protocol) version 14.
+
+use crate::annotations::Annotation;
+use crate::{TestData, TestFailureInfo, TestResult, TestResultValue, TestRun};
+use std::io::Write;
+use std::time::Duration;
+
+/// Writes `test_runs` as a TAP 14 document with a subtest for each package,
+/// which in turn has a subtest for each test binary. A test that ran with
+/// several sets of features is a single test point, which fails if the test
+/// failed with any of them. Failures are located where they were annotated.
+pub fn write<W: Write>(
+    mut w: W,
+    test_runs: &[TestRun],
+    annotations: &[Annotation],
+) -> std::io::Result<()> {
+    let mut packages: Vec<(&str, Vec<&TestRun>)> = Vec::new();
+    for test_run in test_runs {
+        let name = test_run.package.name.as_str();
+        match packages.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, test_runs)) => test_runs.push(test_run),
+            None => packages.push((name, vec![test_run])),
+        }
+    }
+
+    writeln!(w, "TAP version 14")?;
+    writeln!(w, "1..{}", packages.len())?;
+    for (number, (name, test_runs)) in packages.iter().enumerate() {
+        let mut binaries: Vec<Binary> = Vec::new();
+        for test_run in test_runs {
+            for binary in binaries_of(test_run, &test_run.test_run, false)
+                .into_iter()
+                .chain(binaries_of(test_run, &test_run.doc_test_run, true))
+            {
+                match binaries.iter_mut().find(|b| b.name == binary.name) {
+                    Some(existing) => existing.merge(binary),
+                    None => binaries.push(binary),
+                }
+            }
+        }
+
+        writeln!(w, "# Subtest: {}", name)?;
+        writeln!(w, "    1..{}", binaries.len())?;
+        for (number, binary) in binaries.iter().enumerate() {
+            binary.write(&mut w, number + 1, annotations)?;
+        }
+        let ok = binaries.iter().all(|b| b.ok());
+        let time: Duration = test_runs.iter().map(|r| r.total_time()).sum();
+        write_result(&mut w, "", ok, number + 1, name, None)?;
+        write_duration(&mut w, "", time)?;
+    }
+
+    Ok(())
+}
+
+struct Binary<'a> {
+    name: String,
+    time: Option<Duration>,
+    points: Vec<Point<'a>>,
+}
+
+/// A test, with its results with each set of features, along with how long
+/// the test binary ran, since single tests aren't timed.
+struct Point<'a> {
+    name: &'a str,
+    results: Vec<(&'a TestRun, &'a TestResult, Option<Duration>)>,
+}
+
+/// Splits the results of `test_data` by the test binary they ran in.
+fn binaries_of<'a>(
+    test_run: &'a TestRun,
+    test_data: &'a TestData,
+    is_doctest: bool,
+) -> Vec<Binary<'a>> {
+    test_data
+        .results_by_target()
+        .into_iter()
+        .map(|(target, test_results)| {
+            let name = target.unwrap_or("tests");
+            let time = test_data.binary_time(target);
+            Binary {
+                name: if is_doctest {
+                    format!("{} (doctests)", name)
+                } else {
+                    name.to_owned()
+                },
+                time,
+                points: test_results
+                    .into_iter()
+                    .map(|t| Point {
+                        name: &t.name,
+                        results: vec![(test_run, t, time)],
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+impl<'a> Binary<'a> {
+    /// Adds the results of the same binary, built with other features.
+    fn merge(&mut self, other: Binary<'a>) {
+        self.time = self.time.zip(other.time).map(|(a, b)| a + b);
+        for point in other.points {
+            match self.points.iter_mut().find(|p| p.name == point.name) {
+                Some(existing) => existing.results.extend(point.results),
+                None => self.points.push(point),
+            }
+        }
+    }
+
+    fn ok(&self) -> bool {
+        self.points.iter().all(|p| p.failures().next().is_none())
+    }
+
+    fn write<W: Write>(
+        &self,
+        w: &mut W,
+        number: usize,
+        annotations: &[Annotation],
+    ) -> std::io::Result<()> {
+        writeln!(w, "    # Subtest: {}", self.name)?;
+        writeln!(w, "        1..{}", self.points.len())?;
+        for (number, point) in self.points.iter().enumerate() {
+            point.write(w, number + 1, annotations)?;
+        }
+
+        write_result(w, "    ", self.ok(), number, &self.name, None)?;
+        if let Some(time) = self.time {
+            write_duration(w, "    ", time)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Point<'a> {
+    fn failures(
+        &self,
+    ) -> impl Iterator<Item = (&'a TestRun, &'a TestFailureInfo, Option<Duration>)> + '_ {
+        self.results
+            .iter()
+            .filter_map(|(test_run, result, time)| match &result.result {
+                TestResultValue::Failed(failure) => Some((*test_run, failure, *time)),
+                _ => None,
+            })
+    }
+
+    fn write<W: Write>(
+        &self,
+        w: &mut W,
+        number: usize,
+        annotations: &[Annotation],
+    ) -> std::io::Result<()> {
+        let failures: Vec<_> = self.failures().collect();
+        let Some((test_run, failure, time)) = failures.first() else {
+            // Only skipped if it was skipped with every set of features.
+            let skip_reasons: Option<Vec<_>> = self
+                .results
+                .iter()
+                .map(|(_, result, _)| match &result.result {
+                    TestResultValue::Ignored { reason } => {
+                        Some(reason.as_deref().unwrap_or_default())
+                    }
+                    _ => None,
+                })
+                .collect();
+            let skip_reason = skip_reasons.and_then(|r| r.first().copied());
+            return write_result(w, "        ", true, number, self.name, skip_reason);
+        };
+
+        write_result(w, "        ", false, number, self.name, None)?;
+        writeln!(w, "          ---")?;
+        writeln!(
+            w,
+            "          message: {}",
+            yaml_string(&failure.panic_text, 12)
+        )?;
+        writeln!(w, "          severity: fail")?;
+        let annotation = annotations.iter().find(|a| a.is_for(test_run, self.name));
+        if let Some(annotation) = annotation {
+            let target = &annotation.target;
+            writeln!(w, "          at:")?;
+            writeln!(w, "            file: {}", yaml_string(&target.path, 14))?;
+            writeln!(w, "            line: {}", target.line)?;
+            if let Some(column) = target.column {
+                writeln!(w, "            column: {}", column)?;
+            }
+        }
+        if failures.iter().any(|(t, ..)| !t.features.is_empty()) {
+            writeln!(w, "          features:")?;
+            for (test_run, ..) in &failures {
+                // As a flow sequence, which JSON happens to be.
+                let features =
+                    serde_json::to_string(&test_run.features).expect("strings serialize");
+                writeln!(w, "            - {}", features)?;
+            }
+        }
+        if let Some(time) = time {
+            writeln!(w, "          duration_ms: {}", time.as_millis())?;
+        }
+        if !failure.stacktrace.is_empty() {
+            writeln!(
+                w,
+                "          stack: {}",
+                yaml_string(&failure.stacktrace, 12)
+            )?;
+        }
+        writeln!(w, "          ...")
+    }
+}
+
+fn write_result<W: Write>(
+    w: &mut W,
+    indent: &str,
+    ok: bool,
+    number: usize,
+    description: &str,
+    skip_reason: Option<&str>,
+) -> std::io::Result<()> {
+    write!(
+        w,
+        "{}{} {} - {}",
+        indent,
+        if ok { "ok" } else { "not ok" },
+        number,
+        escape_description(description)
+    )?;
+    match skip_reason {
+        Some("") => writeln!(w, " # SKIP"),
+        Some(reason) => writeln!(w, " # SKIP {}", escape_description(reason)),
+        None => writeln!(w),
+    }
+}
+
+fn write_duration<W: Write>(w: &mut W, indent: &str, time: Duration) -> std::io::Result<()> {
+    writeln!(w, "{}  ---", indent)?;
+    writeln!(w, "{}  duration_ms: {}", indent, time.as_millis())?;
+    writeln!(w, "{}  ...", indent)
+}
+
+/// Escapes the characters that have special meaning in the description of a
+/// test point: `#` starts a directive, and `\` escapes.
+fn escape_description(description: &str) -> String {
+    description
+        .replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace(['\r', '\n'], " ")
+}
+
+/// Formats `text` as a YAML scalar: a literal block, indented by `indent`, for
+/// text spanning several lines, or a double-quoted string otherwise.
+fn yaml_string(text: &str, indent: usize) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let text = text.trim_end();
+    if !text.contains('\n') || text.starts_with([' ', '\t']) {
+        return serde_json::to_string(text).expect("strings serialize");
+    }
+
+    let mut block = String::from("|-");
+    for line in text.lines() {
+        block.push('\n');
+        if !line.is_empty() {
+            block.push_str(&" ".repeat(indent));
+            block.push_str(line);
+        }
+    }
+    block
+}
@@ -0,0 +1,193 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! User-configured templates for the title, message and raw details of
+//! annotations, rendered with minijinja.
+
+use crate::flaky::FlakyTest;
+use crate::source::AnnotationTarget;
+use crate::{strip_ansi, TestFailureInfo, TestRun};
+use miette::{Context, IntoDiagnostic};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Templates {
+    /// Replaces the test name as the title.
+    pub title: Option<String>,
+    /// Replaces the features, panic text and stack trace as the message.
+    pub message: Option<String>,
+    /// Replaces the output of the test as the raw details.
+    pub raw_details: Option<String>,
+}
+
+impl Templates {
+    /// Uses the templates of `other` where these aren't set.
+    pub fn or(self, other: Templates) -> Templates {
+        Templates {
+            title: self.title.or(other.title),
+            message: self.message.or(other.message),
+            raw_details: self.raw_details.or(other.raw_details),
+        }
+    }
+
+    /// Compiles the templates that are set.
+    pub fn environment(&self) -> miette::Result<Environment<'static>> {
+        let mut environment = Environment::new();
+        environment.set_trim_blocks(true);
+        for (name, source) in [
+            ("title", &self.title),
+            ("message", &self.message),
+            ("raw-details", &self.raw_details),
+        ] {
+            if let Some(source) = source {
+                environment
+                    .add_template_owned(name, source.clone())
+                    .into_diagnostic()
+                    .with_context(|| format!("invalid {} template", name))?;
+            }
+        }
+        Ok(environment)
+    }
+}
+
+/// Renders the template `name` with `context`, if it's set.
+pub(crate) fn render(
+    environment: &Environment,
+    name: &str,
+    context: &TemplateContext,
+) -> miette::Result<Option<String>> {
+    let Ok(template) = environment.get_template(name) else {
+        return Ok(None);
+    };
+    template
+        .render(context)
+        .map(Some)
+        .into_diagnostic()
+        .with_context(|| format!("could not render {} template for {}", name, context.test))
+}
+
+/// What templates can refer to.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct TemplateContext<'a> {
+    pub package: &'a str,
+    /// The name of the test binary's target.
+    pub target: Option<&'a str>,
+    /// The features of the first test run the test failed in.
+    pub features: &'a [String],
+    /// All feature sets the test failed with.
+    pub feature_sets: &'a [Vec<String>],
+    pub passed_feature_sets: &'a [Vec<String>],
+    pub test: &'a str,
+    pub doctest: bool,
+    pub kind: &'static str,
+    pub panic_text: &'a str,
+    pub assertion: Option<Assertion>,
+    pub backtrace: Vec<Frame>,
+    pub stacktrace: &'a str,
+    pub output: Cow<'a, str>,
+    /// How long the test binary ran, in seconds, since the time of single
+    /// tests isn't reported. Unknown for test results from before binaries
+    /// were timed, if there were several.
+    pub duration: Option<f64>,
+    pub location: &'a AnnotationTarget,
+    /// The numbered lines of source around the location, if it could be read.
+    pub snippet: Option<&'a str>,
+    pub flaky: Option<Flakiness>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Assertion {
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Frame {
+    pub function: String,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Flakiness {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl<'a> TemplateContext<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        test_run: &'a TestRun,
+        test: &'a str,
+        is_doctest: bool,
+        failure: &'a TestFailureInfo,
+        location: &'a AnnotationTarget,
+        feature_sets: &'a [Vec<String>],
+        passed_feature_sets: &'a [Vec<String>],
+        flaky_tests: &[&FlakyTest],
+        snippet: Option<&'a str>,
+    ) -> Self {
+        let test_data = if is_doctest {
+            &test_run.doc_test_run
+        } else {
+            &test_run.test_run
+        };
+        let target = test_data
+            .test_results
+            .iter()
+            .find(|t| t.name == test)
+            .and_then(|t| t.target.as_deref());
+        Self {
+            package: &test_run.package.name,
+            target,
+            features: &test_run.features,
+            feature_sets,
+            passed_feature_sets,
+            test,
+            doctest: is_doctest,
+            kind: failure.kind(is_doctest).id(),
+            panic_text: &failure.panic_text,
+            assertion: failure
+                .assertion_values()
+                .map(|(left, right)| Assertion { left, right }),
+            backtrace: failure
+                .backtrace_frames()
+                .into_iter()
+                .map(|f| Frame {
+                    function: f.function,
+                    file: f.location.as_ref().map(|l| l.file.clone()),
+                    line: f.location.as_ref().map(|l| l.line),
+                    column: f.location.as_ref().map(|l| l.column),
+                })
+                .collect(),
+            stacktrace: &failure.stacktrace,
+            output: strip_ansi(&failure.output),
+            duration: test_data.binary_time(target).map(|d| d.as_secs_f64()),
+            location,
+            snippet,
+            flaky: (!flaky_tests.is_empty()).then(|| Flakiness {
+                passed: flaky_tests.iter().map(|t| t.passed).sum(),
+                failed: flaky_tests.iter().map(|t| t.failed).sum(),
+            }),
+        }
+    }
+}
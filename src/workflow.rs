@@ -0,0 +1,113 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Reporting of annotations as GitHub Actions workflow commands, which need no
+//! token and so also work for pull requests from forks.
+
+use crate::annotations::{Annotation, AnnotationLevel};
+use std::io::Write;
+
+/// The number of annotations of each level GitHub shows per step. Any beyond
+/// that are silently dropped.
+pub const MAX_ANNOTATIONS_PER_LEVEL: usize = 10;
+
+/// Writes a workflow command for each of `annotations`. When there are more
+/// annotations of a level than GitHub will show, the last one shown is replaced
+/// by a summary of the ones that didn't fit.
+pub fn write<W: Write>(mut w: W, annotations: &[Annotation]) -> std::io::Result<()> {
+    for level in [
+        AnnotationLevel::Failure,
+        AnnotationLevel::Warning,
+        AnnotationLevel::Notice,
+    ] {
+        let annotations: Vec<_> = annotations.iter().filter(|a| a.level == level).collect();
+        let (shown, overflow) = if annotations.len() > MAX_ANNOTATIONS_PER_LEVEL {
+            annotations.split_at(MAX_ANNOTATIONS_PER_LEVEL - 1)
+        } else {
+            (&annotations[..], &[][..])
+        };
+
+        for annotation in shown {
+            let target = &annotation.target;
+            let mut properties = vec![
+                ("file", target.path.clone()),
+                ("line", target.line.to_string()),
+                ("endLine", target.line.to_string()),
+            ];
+            if let Some(column) = target.column {
+                properties.push(("col", column.to_string()));
+            }
+            properties.push(("title", annotation.title.clone()));
+            write_command(&mut w, level, &properties, &annotation.message)?;
+        }
+
+        if !overflow.is_empty() {
+            let title = format!(
+                "{} more {} not annotated",
+                overflow.len(),
+                match level {
+                    AnnotationLevel::Failure => "failures",
+                    AnnotationLevel::Warning => "warnings",
+                    AnnotationLevel::Notice => "notices",
+                }
+            );
+            let message = overflow
+                .iter()
+                .map(|a| format!("{} ({})", a.title, a.target))
+                .collect::<Vec<_>>()
+                .join("\n");
+            write_command(&mut w, level, &[("title", title)], &message)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_command<W: Write>(
+    w: &mut W,
+    level: AnnotationLevel,
+    properties: &[(&str, String)],
+    message: &str,
+) -> std::io::Result<()> {
+    let command = match level {
+        AnnotationLevel::Failure => "error",
+        AnnotationLevel::Warning => "warning",
+        AnnotationLevel::Notice => "notice",
+    };
+    let properties = properties
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, escape_property(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(w, "::{} {}::{}", command, properties, escape_data(message))
+}
+
+/// Escapes the message of a workflow command.
+pub fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes the value of a workflow command property, which additionally can't
+/// contain the `:` and `,` that delimit properties.
+pub fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
@@ -0,0 +1,110 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use common::fixture;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::process::{Command, Output};
+
+/// The environment `action.yml` runs us in: every input is passed on, with
+/// its default if it has one and an empty string otherwise.
+fn action_env(inputs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    let action =
+        std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/action.yml")).unwrap();
+    let input = Regex::new(r"^    ([\w-]+):$").unwrap();
+    let default = Regex::new(r#"^        default: "?([^"]*)"?$"#).unwrap();
+    let env = Regex::new(r"^\s+(INPUT_\w+): \$\{\{ inputs\.([\w-]+) \}\}$").unwrap();
+
+    let mut defaults = BTreeMap::new();
+    let mut current = None;
+    let mut vars = BTreeMap::new();
+    for line in action.lines() {
+        if let Some(c) = input.captures(line) {
+            current = Some(c[1].to_owned());
+        } else if let (Some(c), Some(name)) = (default.captures(line), &current) {
+            defaults.insert(name.clone(), c[1].to_owned());
+        } else if let Some(c) = env.captures(line) {
+            let value = inputs
+                .iter()
+                .find(|(name, _)| *name == &c[2])
+                .map(|(_, value)| value.to_string())
+                .or_else(|| defaults.get(&c[2]).cloned())
+                .unwrap_or_default();
+            vars.insert(c[1].to_owned(), value);
+        }
+    }
+    assert!(vars.contains_key("INPUT_BASELINE"));
+    vars
+}
+
+fn run_action(inputs: &[(&str, &str)], step_summary: &str) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-test-annotations"));
+    for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("INPUT_")) {
+        command.env_remove(name);
+    }
+    command
+        .envs(action_env(inputs))
+        .env("GITHUB_STEP_SUMMARY", step_summary)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn ignores_inputs_that_are_not_given() {
+    let fixture = fixture("merged-doctests");
+    let step_summary = concat!(env!("CARGO_TARGET_TMPDIR"), "/action-summary.md");
+    let _ = std::fs::remove_file(step_summary);
+    let metadata = fixture.join("metadata.json");
+    let tests = fixture.join("tests.json");
+    let inputs = [
+        ("metadata", metadata.to_str().unwrap()),
+        ("tests", tests.to_str().unwrap()),
+    ];
+
+    let output = run_action(
+        &[&inputs[..], &[("output", "workflow-commands")]].concat(),
+        step_summary,
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        stdout.contains("::error file=src/lib.rs,line=38"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("4 test failures; tests ran for 0.52s\n"));
+    assert!(std::fs::read_to_string(step_summary)
+        .unwrap()
+        .starts_with("## cargo-test-annotations\n"));
+
+    // Without a token, a check run can't be created.
+    let output = run_action(&inputs, step_summary);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("the `check-run` output needs a GitHub token"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,274 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_metadata::Metadata;
+use cargo_test_annotations::annotations::{self, AnnotationLevel};
+use cargo_test_annotations::flaky::{self, FlakyTest};
+use cargo_test_annotations::paths::RepoPaths;
+use cargo_test_annotations::source::{AnnotationTarget, SourceTree};
+use cargo_test_annotations::template::Templates;
+use cargo_test_annotations::{FailureKind, TestResultValue};
+
+const TESTS: &str = "\
+     Running unittests src/lib.rs (target/debug/deps/fixture-f61697eead3e4da0)
+
+running 1 test
+test parser::tests::it_works ... FAILED
+
+failures:
+
+---- parser::tests::it_works stdout ----
+
+thread 'parser::tests::it_works' panicked at src/parser/mod.rs:8:9:
+explicit panic
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    parser::tests::it_works
+
+test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+";
+
+fn metadata() -> Metadata {
+    common::metadata("source-tree")
+}
+
+#[test]
+fn groups_identical_failures_across_feature_sets() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let test_run = cargo_test_annotations::parse_text(TESTS.as_bytes(), metadata.clone())
+        .unwrap()
+        .remove(0);
+    let mut passing = common::with_features(&test_run, &["std"]);
+    passing.test_run.test_results[0].result = TestResultValue::Ok;
+    let test_runs = [
+        common::with_features(&test_run, &[]),
+        common::with_features(&test_run, &["alloc", "serde"]),
+        passing,
+    ];
+
+    let annotations =
+        annotations::annotations(&test_runs, &source_tree, &Templates::default()).unwrap();
+
+    assert_eq!(annotations.len(), 1);
+    let annotation = &annotations[0];
+    assert_eq!(
+        annotation.target,
+        AnnotationTarget::new("src/parser/mod.rs".to_owned(), 8, Some(9))
+    );
+    assert_eq!(
+        annotation.feature_sets,
+        [Vec::new(), vec!["alloc".to_owned(), "serde".to_owned()]]
+    );
+    assert!(annotation.message.starts_with(
+        "features: [], [alloc, serde]\npassed with features: [std]\n\ncause:\nexplicit panic\n"
+    ));
+    assert!(annotation
+        .message
+        .contains("source:\n   6 |     fn it_works() {\n   7 |         super::parse();\n>  8 |         panic!();\n"));
+    assert!(test_runs
+        .iter()
+        .take(2)
+        .all(|r| annotation.is_for(r, "parser::tests::it_works")));
+}
+
+#[test]
+fn warns_about_flaky_tests() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let failing = cargo_test_annotations::parse_text(TESTS.as_bytes(), metadata.clone())
+        .unwrap()
+        .remove(0);
+    let mut passing = failing.clone();
+    passing.test_run.test_results[0].result = TestResultValue::Ok;
+    let test_runs = [failing.clone(), passing, failing];
+
+    let flaky_tests = flaky::flaky_tests(&test_runs);
+    assert_eq!(
+        flaky_tests.iter().collect::<Vec<_>>(),
+        [&FlakyTest {
+            package: "fixture".to_owned(),
+            features: Vec::new(),
+            name: "parser::tests::it_works".to_owned(),
+            passed: 1,
+            failed: 2,
+        }]
+    );
+
+    let annotations =
+        annotations::annotations(&test_runs, &source_tree, &Templates::default()).unwrap();
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].level, AnnotationLevel::Warning);
+    assert!(annotations[0].flaky);
+    assert!(annotations[0]
+        .message
+        .starts_with("flaky: failed 2 of 3 runs\nfeatures: []\n"));
+    let failures: Vec<_> = annotations::annotated_failures(&test_runs, &annotations).collect();
+    // Flakiness doesn't change what made the test fail.
+    assert!(failures.iter().all(|f| f.kind == FailureKind::Panic));
+}
+
+#[test]
+fn renders_templates() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let tests = TESTS.replace(
+        "explicit panic",
+        "assertion `left == right` failed\n  left: 1\n right: 2",
+    );
+    let test_runs = cargo_test_annotations::parse_text(tests.as_bytes(), metadata).unwrap();
+    let templates = Templates {
+        title: Some("{{ package }}: {{ test }}".to_owned()),
+        message: Some(
+            "{{ kind }} at {{ location.path }}:{{ location.line }}\n\
+             {% if assertion %}{{ assertion.left }} != {{ assertion.right }}{% endif %}"
+                .to_owned(),
+        ),
+        raw_details: None,
+    };
+
+    let annotations = annotations::annotations(&test_runs, &source_tree, &templates).unwrap();
+
+    assert_eq!(annotations.len(), 1);
+    let annotation = &annotations[0];
+    assert_eq!(annotation.test, "parser::tests::it_works");
+    assert_eq!(annotation.title, "fixture: parser::tests::it_works");
+    assert_eq!(
+        annotation.message,
+        "assertion at src/parser/mod.rs:8\n1 != 2"
+    );
+    assert_eq!(
+        annotation.raw_details,
+        "thread 'parser::tests::it_works' panicked at src/parser/mod.rs:8:9:\n\
+         assertion `left == right` failed\n  left: 1\n right: 2\n\
+         note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"
+    );
+}
+
+#[test]
+fn renders_the_duration_of_the_test_binary() {
+    let metadata = common::metadata("multi-binary");
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let test_runs = common::test_runs("multi-binary");
+    let templates = Templates {
+        raw_details: Some("{{ duration }}".to_owned()),
+        ..Templates::default()
+    };
+
+    let annotations = annotations::annotations(&test_runs, &source_tree, &templates).unwrap();
+
+    let durations: Vec<_> = annotations
+        .iter()
+        .map(|a| (a.test.as_str(), a.raw_details.as_str()))
+        .collect();
+    assert_eq!(
+        durations,
+        [
+            ("tests::unit_fails", "0.01"),
+            ("integ_fails", "0.02"),
+            ("src/lib.rs - add_one (line 3)", "0.12"),
+        ]
+    );
+}
+
+#[test]
+fn annotates_oddly_named_doctests_in_the_library() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let mut test_run = cargo_test_annotations::parse_text(TESTS.as_bytes(), metadata)
+        .unwrap()
+        .remove(0);
+    let mut result = test_run.test_run.test_results.remove(0);
+    result.name = "integ_fails".to_owned();
+    test_run.doc_test_run.test_results.push(result);
+
+    let annotations =
+        annotations::annotations(&[test_run], &source_tree, &Templates::default()).unwrap();
+
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(
+        annotations[0].target,
+        AnnotationTarget::new("src/lib.rs".to_owned(), 1, None)
+    );
+}
+
+#[test]
+fn maps_doctest_failures_to_their_source() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+    let located = |tests| {
+        let test_runs = common::text_test_runs("source-tree", tests);
+        annotations::annotations(&test_runs, &source_tree, &Templates::default())
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.test, a.target))
+            .collect::<Vec<_>>()
+    };
+    let target = |line, column| AnnotationTarget::new("src/adder.rs".to_owned(), line, column);
+
+    assert_eq!(
+        located("doctests-standalone.txt"),
+        [
+            (
+                "src/adder.rs - adder::add_one (line 5)".to_owned(),
+                target(7, Some(5))
+            ),
+            (
+                "src/adder.rs - adder::add_two (line 15)".to_owned(),
+                target(18, Some(9))
+            ),
+            (
+                "src/adder.rs - adder::add_two (line 21)".to_owned(),
+                target(22, Some(18))
+            ),
+            (
+                "src/adder.rs - adder::add_two (line 25) - compile fail".to_owned(),
+                target(25, None)
+            ),
+        ]
+    );
+    // Merged doctests panic in a bundle of all of them.
+    assert_eq!(
+        located("doctests-merged.txt"),
+        [
+            (
+                "src/adder.rs - adder::add_one (line 5)".to_owned(),
+                target(5, None)
+            ),
+            (
+                "src/adder.rs - adder::add_two (line 15)".to_owned(),
+                target(15, None)
+            ),
+            (
+                "src/adder.rs - adder::add_two (line 25) - compile fail".to_owned(),
+                target(25, None)
+            ),
+        ]
+    );
+}
@@ -0,0 +1,56 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::baseline::{Baseline, FailureStatus};
+use cargo_test_annotations::TestResultValue;
+use common::test_runs;
+
+#[test]
+fn classifies_failures() {
+    let baseline = Baseline::new(&test_runs("merged-doctests"));
+    // `tests::fails` got fixed, and `tests::passes` broke.
+    let mut test_runs = test_runs("merged-doctests");
+    let results = &mut test_runs[0].test_run.test_results;
+    assert_eq!(results[0].name, "tests::fails");
+    assert_eq!(results[1].name, "tests::passes");
+    results[1].result = std::mem::replace(&mut results[0].result, TestResultValue::Ok);
+
+    let status = |name| baseline.status("merged-doctests", &[], name);
+    assert_eq!(status("tests::passes"), FailureStatus::New);
+    assert_eq!(
+        status("src/lib.rs - add_one (line 7)"),
+        FailureStatus::StillFailing
+    );
+    assert_eq!(
+        baseline.status(
+            "merged-doctests",
+            &["std".to_owned()],
+            "src/lib.rs - add_one (line 7)"
+        ),
+        FailureStatus::New
+    );
+
+    let fixed: Vec<_> = baseline
+        .fixed(&test_runs)
+        .into_iter()
+        .map(|f| f.name.as_str())
+        .collect();
+    assert_eq!(fixed, ["tests::fails"]);
+}
@@ -0,0 +1,89 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use cargo_metadata::{Metadata, MetadataCommand};
+use cargo_test_annotations::annotations::{Annotation, AnnotationLevel};
+use cargo_test_annotations::source::AnnotationTarget;
+use cargo_test_annotations::TestRun;
+use std::path::{Path, PathBuf};
+
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Fixtures that are crates on disk refer to themselves through a `{root}`
+/// placeholder in their metadata.
+pub fn metadata(name: &str) -> Metadata {
+    let fixture = fixture(name);
+    let metadata = std::fs::read_to_string(fixture.join("metadata.json"))
+        .unwrap()
+        .replace("{root}", &fixture.to_string_lossy().replace('\\', "/"));
+    MetadataCommand::parse(metadata).unwrap()
+}
+
+/// Parses the JSON test output of a fixture.
+pub fn test_runs(name: &str) -> Vec<TestRun> {
+    let tests = std::fs::File::open(fixture(name).join("tests.json")).unwrap();
+    cargo_test_annotations::parse(tests, metadata(name)).unwrap()
+}
+
+/// Parses human-readable test output from a file of a fixture.
+pub fn text_test_runs(name: &str, tests: &str) -> Vec<TestRun> {
+    let tests = std::fs::File::open(fixture(name).join(tests)).unwrap();
+    cargo_test_annotations::parse_text(tests, metadata(name)).unwrap()
+}
+
+/// A failure of `test` in the default features of `package`, at the top of
+/// `src/lib.rs`.
+pub fn annotation(package: &str, test: &str) -> Annotation {
+    Annotation {
+        package: package.to_owned(),
+        feature_sets: vec![Vec::new()],
+        test: test.to_owned(),
+        level: AnnotationLevel::Failure,
+        flaky: false,
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), 1, None),
+        title: test.to_owned(),
+        message: String::new(),
+        raw_details: String::new(),
+    }
+}
+
+pub fn with_features(test_run: &TestRun, features: &[&str]) -> TestRun {
+    let mut test_run = test_run.clone();
+    test_run.features = features.iter().map(|f| f.to_string()).collect();
+    test_run
+}
+
+/// Runs `test_run` again with an `extra` feature, where its tests fail the
+/// same way, and groups the failures of both runs in `annotation`.
+pub fn in_two_feature_sets(
+    test_run: TestRun,
+    annotation: Annotation,
+) -> ([TestRun; 2], Annotation) {
+    let extra = with_features(&test_run, &["extra"]);
+    let mut annotation = annotation;
+    annotation.feature_sets = vec![test_run.features.clone(), extra.features.clone()];
+    ([test_run, extra], annotation)
+}
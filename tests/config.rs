@@ -0,0 +1,136 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_metadata::MetadataCommand;
+use cargo_test_annotations::annotations::{Annotation, AnnotationLevel};
+use cargo_test_annotations::config::{Config, ExpiredQuarantine, Expiry};
+use chrono::NaiveDate;
+
+fn annotation(package: &str, test: &str) -> Annotation {
+    Annotation {
+        message: "features: []".to_owned(),
+        ..common::annotation(package, test)
+    }
+}
+
+fn date(date: &str) -> NaiveDate {
+    date.parse().unwrap()
+}
+
+#[test]
+fn quarantines_matching_tests() {
+    let config: Config = toml::from_str(
+        r#"
+[[quarantine]]
+test = "net::*"
+package = "alpha"
+expires = 2024-06-30
+issue = "https://github.com/owner/repo/issues/12"
+
+[[quarantine]]
+test = "slow"
+expires = "2024-01-31"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.quarantine[0].expires,
+        Some(Expiry(date("2024-06-30")))
+    );
+    assert_eq!(
+        config.quarantine[1].expires,
+        Some(Expiry(date("2024-01-31")))
+    );
+
+    let mut annotations = [
+        annotation("alpha", "net::tests::connects"),
+        annotation("beta", "net::tests::connects"),
+        annotation("beta", "slow"),
+    ];
+    let expired = config.quarantine(&mut annotations, date("2024-02-01"));
+
+    assert_eq!(
+        expired,
+        [ExpiredQuarantine {
+            test: "slow".to_owned(),
+            expired: date("2024-01-31"),
+        }]
+    );
+    assert_eq!(annotations[0].level, AnnotationLevel::Warning);
+    assert_eq!(
+        annotations[0].message,
+        "quarantined until 2024-06-30, see https://github.com/owner/repo/issues/12\nfeatures: []"
+    );
+    assert_eq!(annotations[1].level, AnnotationLevel::Failure);
+    // Expired.
+    assert_eq!(annotations[2].level, AnnotationLevel::Failure);
+    assert_eq!(annotations[2].message, "features: []");
+}
+
+fn metadata_with(package_metadata: serde_json::Value) -> cargo_metadata::Metadata {
+    let mut metadata = serde_json::to_value(common::metadata("source-tree")).unwrap();
+    metadata["packages"][0]["metadata"] = package_metadata;
+    MetadataCommand::parse(metadata.to_string()).unwrap()
+}
+
+#[test]
+fn rejects_invalid_patterns() {
+    assert!(toml::from_str::<Config>("[[quarantine]]\ntest = \"net::[\"\n").is_err());
+
+    let metadata = metadata_with(serde_json::json!({
+        "test-annotations": {
+            "quarantine": [{ "test": "parser::[" }],
+        },
+    }));
+    let error = Config::load(&metadata)
+        .unwrap_err()
+        .chain()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(": ");
+    assert!(
+        error.contains("package.metadata.test-annotations"),
+        "{}",
+        error
+    );
+    assert!(
+        error.contains("invalid test pattern `parser::[`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn loads_package_metadata() {
+    let metadata = metadata_with(serde_json::json!({
+        "test-annotations": {
+            "quarantine": [{ "test": "parser::*" }],
+        },
+    }));
+
+    let config = Config::load(&metadata).unwrap();
+
+    assert_eq!(config.quarantine.len(), 1);
+    assert_eq!(config.quarantine[0].package.as_deref(), Some("fixture"));
+    let mut annotations = [annotation("fixture", "parser::tests::it_works")];
+    config.quarantine(&mut annotations, date("2024-02-01"));
+    assert_eq!(annotations[0].level, AnnotationLevel::Warning);
+    assert_eq!(annotations[0].message, "quarantined\nfeatures: []");
+}
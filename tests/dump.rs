@@ -0,0 +1,73 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::dump::{self, DumpSchemaVersionError, SCHEMA_VERSION};
+use cargo_test_annotations::TestRun;
+use common::fixture;
+
+fn test_runs() -> Vec<TestRun> {
+    common::test_runs("merged-doctests")
+}
+
+#[test]
+fn round_trips() {
+    let test_runs = test_runs();
+    let mut written = Vec::new();
+    dump::write(&mut written, &test_runs).unwrap();
+    let read = dump::read(written.as_slice()).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&read).unwrap(),
+        serde_json::to_value(&test_runs).unwrap()
+    );
+}
+
+#[test]
+fn matches_the_current_schema() {
+    let mut written = Vec::new();
+    dump::write(&mut written, &test_runs()).unwrap();
+    let written: serde_json::Value = serde_json::from_slice(&written).unwrap();
+    let expected: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(fixture("merged-doctests").join("dump.json")).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        written, expected,
+        "the dump format changed; bump SCHEMA_VERSION if older readers can't read it, \
+         and update tests/fixtures/merged-doctests/dump.json"
+    );
+    assert_eq!(expected["schema_version"], SCHEMA_VERSION);
+}
+
+#[test]
+fn rejects_other_versions() {
+    for (dump, version) in [
+        (
+            r#"{"schema_version": 1, "test_runs": [{"package": {}}]}"#,
+            Some(1),
+        ),
+        (r#"{"test_runs": []}"#, None),
+    ] {
+        let error = dump::read(dump.as_bytes()).unwrap_err();
+        let error = error.downcast_ref::<DumpSchemaVersionError>().unwrap();
+        assert_eq!(error.0, version);
+    }
+}
@@ -0,0 +1,30 @@
+//! Adding numbers.
+
+/// Adds one.
+///
+/// ```
+/// use fixture::adder::add_one;
+/// assert_eq!(add_one(1), 3);
+/// ```
+pub fn add_one(x: i32) -> i32 {
+    x + 1
+}
+
+/// Adds two.
+///
+/// ```
+/// #![allow(dead_code)]
+/// let x = 1;
+///     assert_eq!(fixture::adder::add_two(x), 4);
+/// ```
+///
+/// ```
+/// let x: i32 = "two";
+/// ```
+///
+/// ```compile_fail
+/// let x: i32 = 2;
+/// ```
+pub fn add_two(x: i32) -> i32 {
+    x + 2
+}
@@ -0,0 +1,6 @@
+//! ```
+//! assert!(false);
+//! ```
+
+pub mod parser;
+pub mod adder;
@@ -0,0 +1,10 @@
+pub fn parse() {}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_works() {
+        super::parse();
+        panic!();
+    }
+}
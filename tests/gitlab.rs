@@ -0,0 +1,111 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::{Annotation, AnnotationLevel};
+use cargo_test_annotations::gitlab;
+use cargo_test_annotations::source::AnnotationTarget;
+use cargo_test_annotations::TestRun;
+use common::{in_two_feature_sets, test_runs};
+
+fn annotation(test: &str, line: u64) -> Annotation {
+    Annotation {
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), line, None),
+        ..common::annotation("merged-doctests", test)
+    }
+}
+
+fn write(test_runs: &[TestRun], annotations: &[Annotation]) -> serde_json::Value {
+    let mut output = Vec::new();
+    gitlab::write(&mut output, test_runs, annotations).unwrap();
+    serde_json::from_slice(&output).unwrap()
+}
+
+#[test]
+fn issues_per_annotated_failure() {
+    let test_runs = test_runs("merged-doctests");
+    let issues = write(
+        &test_runs,
+        &[
+            annotation("tests::fails", 38),
+            annotation("src/lib.rs - add_two (line 22) - compile fail", 22),
+        ],
+    );
+
+    let issues = issues.as_array().unwrap();
+    assert_eq!(issues.len(), 2);
+    assert_eq!(
+        issues[0]["description"],
+        "tests::fails failed: assertion `left == right` failed"
+    );
+    assert_eq!(issues[0]["check_name"], "assertion");
+    assert_eq!(issues[0]["severity"], "critical");
+    assert_eq!(
+        issues[0]["location"],
+        serde_json::json!({ "path": "src/lib.rs", "lines": { "begin": 38 } })
+    );
+    assert_eq!(issues[1]["check_name"], "doctest-compile");
+    assert_ne!(issues[0]["fingerprint"], issues[1]["fingerprint"]);
+}
+
+#[test]
+fn fingerprints_ignore_lines() {
+    let test_runs = test_runs("merged-doctests");
+    let before = write(&test_runs, &[annotation("tests::fails", 38)]);
+    let after = write(&test_runs, &[annotation("tests::fails", 45)]);
+
+    assert_eq!(before[0]["fingerprint"], after[0]["fingerprint"]);
+    assert_eq!(before[0]["fingerprint"].as_str().unwrap().len(), 64);
+}
+
+#[test]
+fn fingerprints_ignore_flakiness() {
+    let test_runs = test_runs("merged-doctests");
+    let before = write(&test_runs, &[annotation("tests::fails", 38)]);
+    let flaky = Annotation {
+        level: AnnotationLevel::Warning,
+        flaky: true,
+        ..annotation("tests::fails", 38)
+    };
+    let after = write(&test_runs, &[flaky]);
+
+    assert_eq!(after[0]["severity"], "minor");
+    assert_eq!(before[0]["check_name"], after[0]["check_name"]);
+    assert_eq!(before[0]["fingerprint"], after[0]["fingerprint"]);
+}
+
+#[test]
+fn single_issue_per_annotation() {
+    // The same test output given twice.
+    let twice = [test_runs("merged-doctests"), test_runs("merged-doctests")].concat();
+    let issues = write(&twice, &[annotation("tests::fails", 38)]);
+    assert_eq!(issues.as_array().unwrap().len(), 1);
+
+    let (test_runs, annotation) = in_two_feature_sets(
+        test_runs("merged-doctests").remove(0),
+        annotation("tests::fails", 38),
+    );
+    let issues = write(&test_runs, &[annotation]);
+    let issues = issues.as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(
+        issues[0]["description"],
+        "tests::fails failed with features [], [extra]: assertion `left == right` failed"
+    );
+}
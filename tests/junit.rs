@@ -0,0 +1,80 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::junit;
+
+fn write_fixture(name: &str, tests: &str) -> String {
+    let test_runs = if name == "merged-doctests" {
+        common::test_runs(name)
+    } else {
+        common::text_test_runs(name, tests)
+    };
+
+    let mut output = Vec::new();
+    junit::write(&mut output, "Tests", &test_runs).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn suite_per_test_binary() {
+    let output = write_fixture("merged-doctests", "tests.json");
+
+    assert!(output.contains(
+        r#"<testsuites name="Tests" tests="6" failures="4" errors="0" skipped="0" time="0.520">"#
+    ));
+    assert!(output.contains(
+        r#"<testsuite name="merged-doctests::merged_doctests" tests="2" failures="1" errors="0" skipped="0" time="0.020">"#
+    ));
+    assert!(output.contains(
+        r#"<testsuite name="merged-doctests::merged_doctests (doctests)" tests="4" failures="3""#
+    ));
+    assert!(output
+        .contains(r#"<testcase classname="merged-doctests::tests" name="fails" time="0.000">"#));
+    assert!(output.contains(
+        r#"<failure message="assertion `left == right` failed&#10;  left: 2&#10; right: 3" type="panic">stack backtrace:"#
+    ));
+    assert!(
+        output.contains("<system-out>thread 'tests::fails' (5697) panicked at src/lib.rs:38:9:\n")
+    );
+    assert_eq!(output.matches("<testcase ").count(), 6);
+    assert!(output.ends_with("  </testsuite>\n</testsuites>\n"));
+}
+
+#[test]
+fn splits_merged_text_runs() {
+    let output = write_fixture("text-workspace", "tests.txt");
+
+    let suites: Vec<_> = output
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("<testsuite name=\""))
+        .map(|l| l.split('"').next().unwrap())
+        .collect();
+    assert!(suites.contains(&"alpha::alpha"));
+    assert!(suites.contains(&"alpha::integration"));
+    assert!(suites.contains(&"alpha::alpha (doctests)"));
+}
+
+#[test]
+fn strips_colors_from_output() {
+    let output = write_fixture("text-workspace", "tests-color.txt");
+
+    assert!(!output.contains('\x1b'));
+    assert!(output.contains("  left: 2\n right: 3\n"));
+}
@@ -0,0 +1,449 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::{TestFailureLocation, TestResultKind, TestResultValue, TestRun};
+use common::{fixture, metadata, test_runs, text_test_runs};
+use std::time::Duration;
+
+#[test]
+fn merged_doctests() {
+    let test_runs = test_runs("merged-doctests");
+    assert_eq!(test_runs.len(), 1);
+    let test_run = &test_runs[0];
+
+    let failure = test_run.test_run.test_results[0]
+        .result
+        .unwrap_failure_ref();
+    assert_eq!(test_run.test_run.test_results[0].name, "tests::fails");
+    assert!(failure
+        .panic_text
+        .starts_with("assertion `left == right` failed"));
+    let location = failure.location.as_ref().unwrap();
+    assert_eq!(
+        (location.file.as_str(), location.line, location.column),
+        ("src/lib.rs", 38, 9)
+    );
+    assert!(failure.stacktrace.starts_with("stack backtrace:"));
+
+    // The merged and the standalone doctests end up in the same test data.
+    let doc_test_run = &test_run.doc_test_run;
+    assert_eq!(doc_test_run.test_count, 4);
+    assert_eq!(doc_test_run.test_results.len(), 4);
+    assert_eq!(doc_test_run.test_summary.result, TestResultKind::Failed);
+    assert_eq!(doc_test_run.test_summary.passed, 1);
+    assert_eq!(doc_test_run.test_summary.failed, 3);
+    assert!(doc_test_run.test_summary.merged_compilation_time.is_some());
+
+    let names: Vec<_> = doc_test_run
+        .test_results
+        .iter()
+        .map(|r| r.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "src/lib.rs - add_one (line 3)",
+            "src/lib.rs - add_one (line 7)",
+            "src/lib.rs - add_two (line 17)",
+            "src/lib.rs - add_two (line 22) - compile fail",
+        ]
+    );
+
+    // Merged doctests panic inside a generated bundle, which is not a useful
+    // location.
+    let merged = doc_test_run.test_results[1].result.unwrap_failure_ref();
+    assert!(merged
+        .panic_text
+        .starts_with("assertion `left == right` failed"));
+    assert!(merged.location.is_none());
+
+    let standalone = doc_test_run.test_results[2].result.unwrap_failure_ref();
+    assert!(standalone
+        .panic_text
+        .starts_with("assertion `left == right` failed"));
+    assert_eq!(standalone.location.as_ref().unwrap().file, "src/lib.rs");
+
+    let compile_fail = doc_test_run.test_results[3].result.unwrap_failure_ref();
+    assert_eq!(
+        compile_fail.panic_text,
+        "Test compiled successfully, but it's marked `compile_fail`."
+    );
+    assert!(compile_fail.location.is_none());
+}
+
+fn targets(test_run: &TestRun) -> Vec<(&str, Option<&str>)> {
+    test_run
+        .test_run
+        .test_results
+        .iter()
+        .chain(&test_run.doc_test_run.test_results)
+        .map(|r| (r.name.as_str(), r.target.as_deref()))
+        .collect()
+}
+
+#[test]
+fn multiple_binaries() {
+    // Without stderr, the binaries are told apart by the order cargo runs
+    // them in, which isn't the order it built them in.
+    for tests in ["tests.json", "tests-stderr.json"] {
+        let tests = std::fs::File::open(fixture("multi-binary").join(tests)).unwrap();
+        let test_runs = cargo_test_annotations::parse(tests, metadata("multi-binary")).unwrap();
+        assert_eq!(test_runs.len(), 1);
+
+        let test_run = &test_runs[0];
+        assert_eq!(test_run.test_run.test_count, 4);
+        assert_eq!(test_run.doc_test_run.test_count, 1);
+        assert_eq!(
+            targets(test_run),
+            [
+                ("tests::unit_fails", Some("multi_binary")),
+                ("tests::unit_passes", Some("multi_binary")),
+                ("integ_fails", Some("integ")),
+                ("integ_passes", Some("integ")),
+                ("src/lib.rs - add_one (line 3)", Some("multi_binary")),
+            ]
+        );
+        let integration = test_run.test_run.test_results[2]
+            .result
+            .unwrap_failure_ref();
+        assert_eq!(integration.panic_text, "integration failure");
+    }
+}
+
+#[test]
+fn concatenated_invocations() {
+    let mut tests = std::fs::read_to_string(fixture("multi-binary").join("tests.json")).unwrap();
+    tests += &tests.clone();
+    let test_runs =
+        cargo_test_annotations::parse(tests.as_bytes(), metadata("multi-binary")).unwrap();
+
+    // Every invocation is a test run of its own.
+    assert_eq!(test_runs.len(), 2);
+    assert_eq!(targets(&test_runs[0]), targets(&test_runs[1]));
+}
+
+/// Rounds to whole milliseconds, since the times are parsed from decimals.
+fn millis(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * 1000.0).round() as u64
+}
+
+#[test]
+fn times() {
+    let merged = test_runs("merged-doctests");
+    let test_run = &merged[0];
+    assert_eq!(millis(test_run.test_run.test_summary.time), 20);
+    assert_eq!(test_run.test_run.test_summary.merged_compilation_time, None);
+    // The merged and the standalone doctests each report their own time,
+    // and the compilation of the merged ones is reported separately.
+    let doc_test_summary = &test_run.doc_test_run.test_summary;
+    assert_eq!(millis(doc_test_summary.time), 190);
+    assert_eq!(
+        doc_test_summary.merged_compilation_time.map(millis),
+        Some(310)
+    );
+    assert_eq!(millis(test_run.total_time()), 520);
+
+    // The times of the binaries of a package add up.
+    let multi_binary = test_runs("multi-binary");
+    let test_run = &multi_binary[0];
+    assert_eq!(millis(test_run.test_run.test_summary.time), 30);
+    let target_time = |target| test_run.test_run.target_time(Some(target)).map(millis);
+    assert_eq!(target_time("multi_binary"), Some(10));
+    assert_eq!(target_time("integ"), Some(20));
+    assert_eq!(millis(test_run.doc_test_run.test_summary.time), 120);
+    assert_eq!(millis(test_run.total_time()), 150);
+}
+
+#[test]
+fn merged_compilation_time_in_result_line() {
+    // Before `all doctests ran in …` was added, the compilation time of merged
+    // doctests was reported in the result line.
+    let tests = "   Doc-tests merged_doctests
+
+running 1 test
+test src/lib.rs - add_one (line 3) ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.25s; merged doctests compilation took 0.20s
+";
+    let test_runs =
+        cargo_test_annotations::parse_text(tests.as_bytes(), metadata("merged-doctests")).unwrap();
+    let doc_test_summary = &test_runs[0].doc_test_run.test_summary;
+    assert_eq!(millis(doc_test_summary.time), 250);
+    assert_eq!(
+        doc_test_summary.merged_compilation_time.map(millis),
+        Some(200)
+    );
+    assert_eq!(millis(test_runs[0].total_time()), 450);
+}
+
+#[test]
+fn several_files() {
+    let metadata = metadata("multi-binary");
+    let fixture = fixture("multi-binary");
+    let pattern = fixture.join("tests*.json");
+    let test_runs = cargo_test_annotations::parse_files(pattern.to_str().unwrap(), |file| {
+        cargo_test_annotations::parse(file, metadata.clone())
+    })
+    .unwrap();
+
+    // One test run from each file, in alphabetical order.
+    let source_files: Vec<_> = test_runs
+        .iter()
+        .map(|t| t.source_file.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        source_files,
+        [
+            fixture.join("tests-stderr.json").to_str().unwrap(),
+            fixture.join("tests.json").to_str().unwrap()
+        ]
+    );
+    for test_run in &test_runs {
+        assert_eq!(test_run.package.name, "multi-binary");
+        assert_eq!(test_run.test_run.test_count, 4);
+        assert_eq!(test_run.doc_test_run.test_count, 1);
+    }
+
+    let error = cargo_test_annotations::parse_files(
+        fixture.join("missing*.json").to_str().unwrap(),
+        |_| unreachable!(),
+    )
+    .unwrap_err();
+    assert!(error.to_string().starts_with("no test output files match"));
+}
+
+#[test]
+fn text_workspace() {
+    let test_runs = text_test_runs("text-workspace", "tests.txt");
+    assert_eq!(test_runs.len(), 2);
+
+    // The unit and integration tests of a package are combined.
+    let alpha = &test_runs[0];
+    assert_eq!(alpha.package.name, "alpha");
+    assert!(alpha.features.is_empty());
+    assert_eq!(alpha.test_run.test_count, 4);
+    assert_eq!(alpha.test_run.test_summary.passed, 2);
+    assert_eq!(alpha.test_run.test_summary.failed, 2);
+    let failed: Vec<_> = alpha
+        .test_run
+        .test_results
+        .iter()
+        .filter(|r| matches!(r.result, TestResultValue::Failed(_)))
+        .map(|r| {
+            let failure = r.result.unwrap_failure_ref();
+            (
+                r.name.as_str(),
+                failure.panic_text.lines().next().unwrap(),
+                failure.location.as_ref().unwrap().file.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        failed,
+        [
+            ("tests::fails", "oh no", "crates/alpha/src/lib.rs"),
+            (
+                "integration_fails",
+                "assertion `left == right` failed: three doubled",
+                "crates/alpha/tests/integration.rs"
+            ),
+        ]
+    );
+    assert_eq!(alpha.doc_test_run.test_count, 1);
+    assert_eq!(alpha.doc_test_run.test_summary.passed, 1);
+
+    // Both packages have a `src/lib.rs`; the executable name tells them apart.
+    let beta_gamma = &test_runs[1];
+    assert_eq!(beta_gamma.package.name, "beta-gamma");
+    assert_eq!(beta_gamma.test_run.test_count, 1);
+    assert_eq!(beta_gamma.doc_test_run.test_count, 1);
+    assert_eq!(beta_gamma.doc_test_run.test_summary.failed, 1);
+    assert!(beta_gamma
+        .doc_test_run
+        .test_summary
+        .merged_compilation_time
+        .is_some());
+}
+
+#[test]
+fn text_with_colors() {
+    let test_runs = text_test_runs("text-workspace", "tests-color.txt");
+    assert_eq!(test_runs.len(), 1);
+
+    let beta_gamma = &test_runs[0];
+    assert_eq!(beta_gamma.package.name, "beta-gamma");
+    assert_eq!(beta_gamma.test_run.test_results.len(), 1);
+    assert!(matches!(
+        beta_gamma.test_run.test_results[0].result,
+        TestResultValue::Ok
+    ));
+    assert_eq!(beta_gamma.test_run.test_summary.result, TestResultKind::Ok);
+    assert_eq!(beta_gamma.doc_test_run.test_results.len(), 1);
+    assert_eq!(
+        beta_gamma.doc_test_run.test_summary.result,
+        TestResultKind::Failed
+    );
+    let failure = beta_gamma.doc_test_run.test_results[0]
+        .result
+        .unwrap_failure_ref();
+    assert_eq!(
+        failure.panic_text,
+        "assertion `left == right` failed\n  left: 2\n right: 3"
+    );
+    // The output is kept as it was printed, for the terminal.
+    assert!(failure
+        .output
+        .contains("  left: \x1b[31m2\x1b[0m\n right: \x1b[32m3\x1b[0m"));
+}
+
+fn test_outcomes(test_run: &TestRun) -> Vec<(&str, &'static str)> {
+    test_run
+        .test_run
+        .test_results
+        .iter()
+        .map(|r| {
+            let outcome = match r.result {
+                TestResultValue::Ok => "ok",
+                TestResultValue::Failed(_) => "FAILED",
+                TestResultValue::Ignored { .. } => "ignored",
+            };
+            (r.name.as_str(), outcome)
+        })
+        .collect()
+}
+
+#[test]
+fn nocapture_single_threaded() {
+    let test_runs = text_test_runs("nocapture", "tests-single-threaded.txt");
+    assert_eq!(test_runs.len(), 1);
+    let test_run = &test_runs[0];
+
+    // `tests::prints_and_fails` prints `test tests::prints_and_passes ...
+    // FAILED`, and `tests::prints_and_passes` prints `test tests::imaginary
+    // ... ok`, neither of which are results.
+    assert_eq!(
+        test_outcomes(test_run),
+        [
+            ("tests::plain_failure", "FAILED"),
+            ("tests::prints_and_fails", "FAILED"),
+            ("tests::prints_and_passes", "ok"),
+            ("tests::prints_without_newline", "ok"),
+            ("tests::slow_pass", "ok"),
+        ]
+    );
+
+    let failure = test_run.test_run.test_results[1]
+        .result
+        .unwrap_failure_ref();
+    assert_eq!(
+        failure.panic_text,
+        "assertion `left == right` failed: math is broken\n  left: 2\n right: 3"
+    );
+    let location = failure.location.as_ref().unwrap();
+    assert_eq!((location.file.as_str(), location.line), ("src/lib.rs", 18));
+}
+
+#[test]
+fn nocapture_multi_threaded() {
+    let test_runs = text_test_runs("nocapture", "tests-multi-threaded.txt");
+    assert_eq!(test_runs.len(), 1);
+    let test_run = &test_runs[0];
+
+    // The `test tests::prints_and_passes ... FAILED` printed by
+    // `tests::prints_and_fails` contradicts the listing of failed tests, and
+    // the `test tests::imaginary ... ok` printed by `tests::prints_and_passes`
+    // is one passed test too many.
+    assert_eq!(
+        test_outcomes(test_run),
+        [
+            ("tests::plain_failure", "FAILED"),
+            ("tests::prints_and_fails", "FAILED"),
+            ("tests::prints_and_passes", "ok"),
+            ("tests::prints_without_newline", "ok"),
+            ("tests::slow_pass", "ok"),
+        ]
+    );
+
+    let failure = test_run.test_run.test_results[0]
+        .result
+        .unwrap_failure_ref();
+    assert_eq!(failure.panic_text, "plain");
+    let location = failure.location.as_ref().unwrap();
+    assert_eq!((location.file.as_str(), location.line), ("src/lib.rs", 23));
+}
+
+#[test]
+fn nocapture_stray_results() {
+    let test_runs = text_test_runs("nocapture", "tests-stray-results.txt");
+    assert_eq!(test_runs.len(), 1);
+
+    // Results that follow output of a test are dropped, earliest first, until
+    // the passed and ignored tests add up to the summary.
+    assert_eq!(
+        test_outcomes(&test_runs[0]),
+        [
+            ("tests::not_ready", "ignored"),
+            ("tests::quiet_pass", "ok"),
+            ("tests::prints_results", "ok"),
+        ]
+    );
+}
+
+#[test]
+fn windows_paths() {
+    let test_runs = text_test_runs("windows", "tests.txt");
+    assert_eq!(test_runs.len(), 1);
+    let test_run = &test_runs[0];
+    assert_eq!(test_run.package.name, "merged-doctests");
+
+    let failure = test_run.test_run.test_results[0]
+        .result
+        .unwrap_failure_ref();
+    let location = failure.location.as_ref().unwrap();
+    assert_eq!(
+        (location.file.as_str(), location.line, location.column),
+        ("src/lib.rs", 38, 9)
+    );
+
+    let doc_test_run = &test_run.doc_test_run;
+    assert_eq!(doc_test_run.test_results.len(), 4);
+    assert!(doc_test_run.test_results[1]
+        .result
+        .unwrap_failure_ref()
+        .location
+        .is_none());
+    let standalone = doc_test_run.test_results[2].result.unwrap_failure_ref();
+    assert_eq!(standalone.location.as_ref().unwrap().file, "src/lib.rs");
+}
+
+#[test]
+fn windows_locations() {
+    let location: TestFailureLocation = r"C:\a\src\lib.rs:10:5".parse().unwrap();
+    assert_eq!(
+        (location.file.as_str(), location.line, location.column),
+        ("C:/a/src/lib.rs", 10, 5)
+    );
+
+    let location: TestFailureLocation = r"src\lib.rs:10:5".parse().unwrap();
+    assert_eq!(location.file, "src/lib.rs");
+
+    assert!(r":10:5".parse::<TestFailureLocation>().is_err());
+    assert!(r"src\lib.rs:10".parse::<TestFailureLocation>().is_err());
+}
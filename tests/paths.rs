@@ -0,0 +1,114 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::paths::RepoPaths;
+use common::metadata;
+
+#[test]
+fn resolves_against_workspace_root() {
+    let metadata = metadata("text-workspace");
+    let alpha = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, "crates/alpha/src/lib.rs")
+            .as_deref(),
+        Some("crates/alpha/src/lib.rs")
+    );
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, "./crates/alpha/tests/../src/lib.rs")
+            .as_deref(),
+        Some("crates/alpha/src/lib.rs")
+    );
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, "/tmp/fx/text-workspace/crates/alpha/src/lib.rs")
+            .as_deref(),
+        Some("crates/alpha/src/lib.rs")
+    );
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, r"crates\alpha\src\lib.rs")
+            .as_deref(),
+        Some("crates/alpha/src/lib.rs")
+    );
+}
+
+#[test]
+fn resolves_with_path_prefix() {
+    let metadata = metadata("text-workspace");
+    let alpha = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "rust/");
+
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, "crates/alpha/src/lib.rs")
+            .as_deref(),
+        Some("rust/crates/alpha/src/lib.rs")
+    );
+    assert_eq!(
+        repo_paths.resolve(alpha, "../docs/README.md").as_deref(),
+        Some("docs/README.md")
+    );
+    assert_eq!(
+        repo_paths
+            .resolve(alpha, "/tmp/fx/vendor/beta/src/lib.rs")
+            .as_deref(),
+        Some("vendor/beta/src/lib.rs")
+    );
+}
+
+#[test]
+fn resolves_packages_outside_workspace_against_repository_root() {
+    let metadata = metadata("outside-workspace");
+    let gamma = &metadata.workspace_packages()[1];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "workspace/");
+
+    assert_eq!(
+        repo_paths.resolve(gamma, "src/lib.rs").as_deref(),
+        Some("vendor/gamma/src/lib.rs")
+    );
+    assert_eq!(
+        repo_paths.resolve(gamma, "./src/../src/lib.rs").as_deref(),
+        Some("vendor/gamma/src/lib.rs")
+    );
+}
+
+#[test]
+fn rejects_paths_outside_repository() {
+    let metadata = metadata("text-workspace");
+    let alpha = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+
+    assert_eq!(
+        repo_paths.resolve(
+            alpha,
+            "/rustc/59807616e1fa2540724bfbac14d7976d7e4a3860/library/core/src/panicking.rs"
+        ),
+        None
+    );
+    assert_eq!(repo_paths.resolve(alpha, "../outside.rs"), None);
+    assert_eq!(
+        repo_paths.resolve(alpha, r"C:\Users\runner\.cargo\registry\src\lib.rs"),
+        None
+    );
+}
@@ -0,0 +1,99 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::Annotation;
+use cargo_test_annotations::reviewdog;
+use cargo_test_annotations::source::AnnotationTarget;
+use common::test_runs;
+
+fn annotations() -> Vec<Annotation> {
+    [
+        ("tests::fails", 38, Some(9)),
+        ("src/lib.rs - add_two (line 22) - compile fail", 22, None),
+    ]
+    .into_iter()
+    .map(|(test, line, column)| Annotation {
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), line, column),
+        ..common::annotation("merged-doctests", test)
+    })
+    .collect()
+}
+
+#[test]
+fn rdjsonl() {
+    let mut output = Vec::new();
+    reviewdog::write_rdjsonl(&mut output, &test_runs("merged-doctests"), &annotations()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    let diagnostics: Vec<serde_json::Value> = output
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(
+        diagnostics[0],
+        serde_json::json!({
+            "message": "tests::fails failed: assertion `left == right` failed\n  left: 2\n right: 3",
+            "location": {
+                "path": "src/lib.rs",
+                "range": { "start": { "line": 38, "column": 9 } },
+            },
+            "severity": "ERROR",
+            "source": { "name": "cargo-test" },
+            "code": { "value": "assertion" },
+        })
+    );
+    assert_eq!(diagnostics[1]["code"]["value"], "doctest-compile");
+}
+
+#[test]
+fn checkstyle() {
+    let mut output = Vec::new();
+    reviewdog::write_checkstyle(&mut output, &test_runs("merged-doctests"), &annotations())
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert_eq!(
+        output,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="src/lib.rs">
+    <error line="38" column="9" severity="error" message="tests::fails failed: assertion `left == right` failed&#10;  left: 2&#10; right: 3" source="cargo-test.assertion"/>
+    <error line="22" severity="error" message="src/lib.rs - add_two (line 22) - compile fail failed: Test compiled successfully, but it's marked `compile_fail`." source="cargo-test.doctest-compile"/>
+  </file>
+</checkstyle>
+"#
+    );
+}
+
+#[test]
+fn single_diagnostic_per_annotation() {
+    let (test_runs, annotation) = common::in_two_feature_sets(
+        test_runs("merged-doctests").remove(0),
+        annotations().remove(0),
+    );
+
+    let mut output = Vec::new();
+    reviewdog::write_rdjsonl(&mut output, &test_runs, &[annotation]).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert_eq!(output.lines().count(), 1);
+    assert!(output.contains("tests::fails failed"));
+}
@@ -0,0 +1,110 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::run::{manifest_path, Echo};
+use common::{fixture, metadata};
+use std::ffi::OsString;
+use std::io::Read;
+
+#[test]
+fn echoes_human_readable_output() {
+    let tests = std::fs::read_to_string(fixture("merged-doctests").join("tests.json")).unwrap();
+    // Only the unit tests, as with `cargo test --lib`.
+    let lines: Vec<_> = tests.lines().take(41).collect();
+
+    let artifact: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    let compiler_message = serde_json::json!({
+        "reason": "compiler-message",
+        "package_id": artifact["package_id"],
+        "manifest_path": artifact["manifest_path"],
+        "target": artifact["target"],
+        "message": {
+            "message": "unused variable: `x`",
+            "code": null,
+            "level": "warning",
+            "spans": [],
+            "children": [],
+            "rendered": "warning: unused variable: `x`\n",
+        },
+    });
+    let input = format!(
+        "{}\n{}\n{}\n",
+        compiler_message,
+        lines[..3].join("\n"),
+        lines[3..].join("\n")
+    );
+
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let mut echo = Echo::new(input.as_bytes(), &mut out, &mut err);
+    let test_runs = cargo_test_annotations::parse(&mut echo, metadata("merged-doctests")).unwrap();
+    let mut rest = Vec::new();
+    echo.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    assert_eq!(test_runs.len(), 1);
+    assert_eq!(test_runs[0].test_run.test_results.len(), 2);
+    assert!(test_runs[0].doc_test_run.test_results.is_empty());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("{}\n", lines[3..].join("\n"))
+    );
+    assert_eq!(
+        String::from_utf8(err).unwrap(),
+        "warning: unused variable: `x`\n"
+    );
+}
+
+#[test]
+fn echoes_cargo_status_to_stderr() {
+    let input = std::fs::read_to_string(fixture("multi-binary").join("tests-stderr.json")).unwrap();
+
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let mut echo = Echo::new(input.as_bytes(), &mut out, &mut err);
+    let test_runs = cargo_test_annotations::parse(&mut echo, metadata("multi-binary")).unwrap();
+    assert_eq!(test_runs.len(), 1);
+    assert_eq!(test_runs[0].test_run.test_results.len(), 4);
+
+    let err = String::from_utf8(err).unwrap();
+    assert!(err.starts_with("   Compiling multi-binary v0.1.0"));
+    assert!(err.contains("     Running tests/integ.rs"));
+    assert!(
+        err.ends_with("error: 3 targets failed:\n    `--lib`\n    `--test integ`\n    `--doc`\n")
+    );
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("\nrunning 2 tests\n"));
+    assert!(!out.contains("Running"));
+}
+
+#[test]
+fn finds_manifest_path() {
+    let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+    assert_eq!(
+        manifest_path(&args(&["--manifest-path", "a/Cargo.toml", "--lib"])),
+        Some("a/Cargo.toml".into())
+    );
+    assert_eq!(
+        manifest_path(&args(&["--lib", "--manifest-path=b/Cargo.toml"])),
+        Some("b/Cargo.toml".into())
+    );
+    assert_eq!(
+        manifest_path(&args(&["--", "--manifest-path", "c/Cargo.toml"])),
+        None
+    );
+}
@@ -0,0 +1,143 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::Annotation;
+use cargo_test_annotations::paths::RepoPaths;
+use cargo_test_annotations::sarif;
+use cargo_test_annotations::source::AnnotationTarget;
+use common::{annotation, in_two_feature_sets, metadata, test_runs};
+
+fn failure_at_line_38() -> Annotation {
+    Annotation {
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), 38, Some(9)),
+        ..annotation("merged-doctests", "tests::fails")
+    }
+}
+
+#[test]
+fn results_per_failure() {
+    let repo_paths = RepoPaths::with_path_prefix(&metadata("merged-doctests"), "");
+    let test_runs = test_runs("merged-doctests");
+    let annotations = [failure_at_line_38()];
+
+    let mut output = Vec::new();
+    sarif::write(&mut output, &test_runs, &annotations, &repo_paths).unwrap();
+    let log: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    let rules: Vec<_> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        rules,
+        ["panic", "assertion", "timeout", "crash", "doctest-compile"]
+    );
+
+    let results = run["results"].as_array().unwrap();
+    let rule_ids: Vec<_> = results
+        .iter()
+        .map(|r| r["ruleId"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        rule_ids,
+        ["assertion", "assertion", "assertion", "doctest-compile"]
+    );
+
+    let result = &results[0];
+    assert_eq!(result["ruleIndex"], 1);
+    assert_eq!(
+        result["locations"][0]["physicalLocation"],
+        serde_json::json!({
+            "artifactLocation": { "uri": "src/lib.rs", "uriBaseId": "%SRCROOT%" },
+            "region": { "startLine": 38, "startColumn": 9 },
+        })
+    );
+    let flow = result["codeFlows"][0]["threadFlows"][0]["locations"]
+        .as_array()
+        .unwrap();
+    assert_eq!(flow.len(), 2);
+    assert_eq!(
+        flow[1]["location"]["message"]["text"],
+        "merged_doctests::tests::fails"
+    );
+    assert_eq!(
+        flow[1]["location"]["physicalLocation"]["region"]["startLine"],
+        38
+    );
+
+    // Not annotated, so it's located in the source file of its target.
+    assert_eq!(
+        results[1]["locations"][0]["physicalLocation"],
+        serde_json::json!({
+            "artifactLocation": { "uri": "src/lib.rs", "uriBaseId": "%SRCROOT%" },
+            "region": { "startLine": 1 },
+        })
+    );
+}
+
+#[test]
+fn valid_sarif() {
+    let repo_paths = RepoPaths::with_path_prefix(&metadata("merged-doctests"), "");
+    let test_runs = test_runs("merged-doctests");
+    let annotations = [Annotation {
+        flaky: true,
+        ..failure_at_line_38()
+    }];
+
+    let mut output = Vec::new();
+    sarif::write(&mut output, &test_runs, &annotations, &repo_paths).unwrap();
+    let log: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    let schema =
+        std::fs::read_to_string(common::fixture("sarif").join("sarif-schema-2.1.0.json")).unwrap();
+    let validator = jsonschema::options()
+        .should_validate_formats(true)
+        .build(&serde_json::from_str(&schema).unwrap())
+        .unwrap();
+    let errors: Vec<_> = validator
+        .iter_errors(&log)
+        .map(|e| format!("{}: {}", e.instance_path, e))
+        .collect();
+    assert!(errors.is_empty(), "{:#?}", errors);
+}
+
+#[test]
+fn single_result_per_annotation() {
+    let repo_paths = RepoPaths::with_path_prefix(&metadata("merged-doctests"), "");
+    let mut default = test_runs("merged-doctests").remove(0);
+    // Only the failure that's annotated.
+    default.doc_test_run.test_results.clear();
+    let (test_runs, annotation) = in_two_feature_sets(default, failure_at_line_38());
+
+    let mut output = Vec::new();
+    sarif::write(&mut output, &test_runs, &[annotation], &repo_paths).unwrap();
+    let log: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0]["properties"]["featureSets"],
+        serde_json::json!([[], ["extra"]])
+    );
+}
@@ -0,0 +1,147 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_metadata::Metadata;
+use cargo_test_annotations::paths::RepoPaths;
+use cargo_test_annotations::source::{AnnotationTarget, Placement, SourceTree};
+
+fn metadata() -> Metadata {
+    common::metadata("source-tree")
+}
+
+fn target(path: &str, line: u64, column: Option<u64>) -> AnnotationTarget {
+    AnnotationTarget::new(path.to_owned(), line, column)
+}
+
+#[test]
+fn keeps_existing_locations() {
+    let metadata = metadata();
+    let package = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+
+    assert_eq!(
+        source_tree.place(
+            package,
+            None,
+            "parser::tests::it_works",
+            false,
+            Some(target("src/parser/mod.rs", 8, Some(9)))
+        ),
+        Some((
+            target("src/parser/mod.rs", 8, Some(9)),
+            Placement::AsReported
+        ))
+    );
+}
+
+#[test]
+fn falls_back_to_test_function() {
+    let metadata = metadata();
+    let package = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+
+    // Past the end of the file.
+    assert_eq!(
+        source_tree.place(
+            package,
+            None,
+            "parser::tests::it_works",
+            false,
+            Some(target("src/parser/mod.rs", 80, Some(9)))
+        ),
+        Some((
+            target("src/parser/mod.rs", 6, None),
+            Placement::TestFunction
+        ))
+    );
+    // A file that doesn't exist, for a test in a differently named module.
+    assert_eq!(
+        source_tree.place(
+            package,
+            None,
+            "integration::it_works",
+            false,
+            Some(target("src/generated.rs", 1, None))
+        ),
+        Some((
+            target("tests/integration.rs", 2, None),
+            Placement::TestFunction
+        ))
+    );
+}
+
+#[test]
+fn falls_back_to_target_file() {
+    let metadata = metadata();
+    let package = &metadata.workspace_packages()[0];
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+
+    assert_eq!(
+        source_tree.place(package, None, "src/lib.rs - (line 1)", true, None),
+        Some((target("src/lib.rs", 1, None), Placement::TargetFile))
+    );
+    assert_eq!(
+        source_tree.place(package, None, "tests::does_not_exist", false, None),
+        Some((target("src/lib.rs", 1, None), Placement::TargetFile))
+    );
+    // The target whose test binary ran the test, when it's known.
+    assert_eq!(
+        source_tree.place(package, Some("integration"), "does_not_exist", false, None),
+        Some((
+            target("tests/integration.rs", 1, None),
+            Placement::TargetFile
+        ))
+    );
+}
+
+#[test]
+fn snippets_mark_the_target_line() {
+    let metadata = metadata();
+    let repo_paths = RepoPaths::with_path_prefix(&metadata, "");
+    let source_tree = SourceTree::new(&repo_paths);
+
+    assert_eq!(
+        source_tree
+            .snippet(&target("src/parser/mod.rs", 8, Some(9)), 2)
+            .unwrap(),
+        concat!(
+            "   6 |     fn it_works() {\n",
+            "   7 |         super::parse();\n",
+            ">  8 |         panic!();\n",
+            "     |         ^\n",
+            "   9 |     }\n",
+            "  10 | }",
+        )
+    );
+    // Doctests are shown in their doc comment.
+    assert_eq!(
+        source_tree
+            .snippet(&target("src/lib.rs", 2, None), 1)
+            .unwrap(),
+        "  1 | //! ```\n> 2 | //! assert!(false);\n  3 | //! ```"
+    );
+    assert_eq!(
+        source_tree.snippet(&target("src/parser/mod.rs", 80, None), 2),
+        None
+    );
+}
@@ -0,0 +1,55 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::Annotation;
+use cargo_test_annotations::source::AnnotationTarget;
+use cargo_test_annotations::summary::{self, BlobLinks};
+use common::{annotation, test_runs};
+
+#[test]
+fn summarizes_packages_and_failures() {
+    let test_runs = test_runs("merged-doctests");
+    let annotations = [Annotation {
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), 38, Some(9)),
+        ..annotation("merged-doctests", "tests::fails")
+    }];
+    let links = BlobLinks::new("https://github.com/", "owner/repo", "0123abc");
+
+    let mut output = Vec::new();
+    summary::write(
+        &mut output,
+        "Tests",
+        &test_runs,
+        &annotations,
+        Some(&links),
+        None,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.starts_with("## Tests\n\n❌ 4 test failures; tests ran for 0.52s\n"));
+    assert!(output.contains("| merged-doctests | 2 | 4 | 0 | 0 | 0.52s |\n"));
+    assert_eq!(output.matches("<details>").count(), 4);
+    assert!(output.contains(
+        "<summary><code>tests::fails</code> in <code>merged-doctests</code></summary>\n\n\
+         At [src/lib.rs:38:9](https://github.com/owner/repo/blob/0123abc/src/lib.rs#L38)\n"
+    ));
+    assert!(output.contains("```text\nassertion `left == right` failed\n"));
+}
@@ -0,0 +1,139 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::Annotation;
+use cargo_test_annotations::source::AnnotationTarget;
+use cargo_test_annotations::tap;
+use cargo_test_annotations::TestRun;
+use common::{annotation, in_two_feature_sets, metadata};
+
+const TESTS: &str = "\
+     Running unittests src/lib.rs (target/debug/deps/alpha-f61697eead3e4da0)
+
+running 3 tests
+test tests::fails ... FAILED
+test tests::passes ... ok
+test tests::slow ... ignored, takes #1 hour
+
+failures:
+
+---- tests::fails stdout ----
+
+thread 'tests::fails' panicked at crates/alpha/src/lib.rs:12:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    tests::fails
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.25s
+
+     Running tests/integration.rs (target/debug/deps/integration-45f6873b4ab5ecdd)
+
+running 1 test
+test integration_passes ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+
+";
+
+fn test_runs() -> Vec<TestRun> {
+    cargo_test_annotations::parse_text(TESTS.as_bytes(), metadata("text-workspace")).unwrap()
+}
+
+fn failure() -> Annotation {
+    Annotation {
+        target: AnnotationTarget::new("crates/alpha/src/lib.rs".to_owned(), 12, Some(9)),
+        ..annotation("alpha", "tests::fails")
+    }
+}
+
+#[test]
+fn subtests_per_package_and_binary() {
+    let test_runs = test_runs();
+    let annotations = [failure()];
+
+    let mut output = Vec::new();
+    tap::write(&mut output, &test_runs, &annotations).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert_eq!(
+        output,
+        r#"TAP version 14
+1..1
+# Subtest: alpha
+    1..2
+    # Subtest: alpha
+        1..3
+        not ok 1 - tests::fails
+          ---
+          message: |-
+            assertion `left == right` failed
+              left: 1
+             right: 2
+          severity: fail
+          at:
+            file: "crates/alpha/src/lib.rs"
+            line: 12
+            column: 9
+          duration_ms: 250
+          ...
+        ok 2 - tests::passes
+        ok 3 - tests::slow # SKIP takes \#1 hour
+    not ok 1 - alpha
+      ---
+      duration_ms: 250
+      ...
+    # Subtest: integration
+        1..1
+        ok 1 - integration_passes
+    ok 2 - integration
+      ---
+      duration_ms: 10
+      ...
+not ok 1 - alpha
+  ---
+  duration_ms: 260
+  ...
+"#
+    );
+}
+
+#[test]
+fn single_point_per_test_across_features() {
+    let (test_runs, annotation) = in_two_feature_sets(test_runs().remove(0), failure());
+
+    let mut output = Vec::new();
+    tap::write(&mut output, &test_runs, &[annotation]).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert_eq!(output.matches("not ok 1 - tests::fails").count(), 1);
+    assert!(output.contains(
+        r#"          features:
+            - []
+            - ["extra"]
+"#
+    ));
+    assert!(output.contains("    # Subtest: alpha\n        1..3\n"));
+    assert!(output.ends_with("not ok 1 - alpha\n  ---\n  duration_ms: 520\n  ...\n"));
+}
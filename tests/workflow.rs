@@ -0,0 +1,78 @@
+// Copyright 2022 Alexander Krivács Schrøder
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// OR
+//
+// Licensed under the MIT License. See LICENSE-MIT for details.
+
+mod common;
+
+use cargo_test_annotations::annotations::{Annotation, AnnotationLevel};
+use cargo_test_annotations::source::AnnotationTarget;
+use cargo_test_annotations::workflow::{self, MAX_ANNOTATIONS_PER_LEVEL};
+
+fn annotation(level: AnnotationLevel, title: &str, message: &str) -> Annotation {
+    Annotation {
+        level,
+        target: AnnotationTarget::new("src/lib.rs".to_owned(), 12, Some(5)),
+        message: message.to_owned(),
+        ..common::annotation("fixture", title)
+    }
+}
+
+fn write(annotations: &[Annotation]) -> String {
+    let mut output = Vec::new();
+    workflow::write(&mut output, annotations).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn escapes_commands() {
+    let output = write(&[annotation(
+        AnnotationLevel::Failure,
+        "tests::a, b: c",
+        "100% broken\r\nsee below\n",
+    )]);
+
+    assert_eq!(
+        output,
+        "::error file=src/lib.rs,line=12,endLine=12,col=5,title=tests%3A%3Aa%2C b%3A c::100%25 broken%0D%0Asee below%0A\n"
+    );
+}
+
+#[test]
+fn summarizes_overflow() {
+    let annotations: Vec<_> = (0..MAX_ANNOTATIONS_PER_LEVEL + 2)
+        .map(|i| {
+            annotation(
+                AnnotationLevel::Failure,
+                &format!("tests::t{}", i),
+                "failed",
+            )
+        })
+        .chain([annotation(AnnotationLevel::Warning, "tests::w", "flaky")])
+        .collect();
+    let output = write(&annotations);
+    let lines: Vec<_> = output.lines().collect();
+
+    assert_eq!(lines.len(), MAX_ANNOTATIONS_PER_LEVEL + 1);
+    assert!(lines[..MAX_ANNOTATIONS_PER_LEVEL - 1]
+        .iter()
+        .all(|l| l.starts_with("::error file=src/lib.rs,")));
+    assert_eq!(
+        lines[MAX_ANNOTATIONS_PER_LEVEL - 1],
+        "::error title=3 more failures not annotated::tests::t9 (src/lib.rs:12:5)%0Atests::t10 (src/lib.rs:12:5)%0Atests::t11 (src/lib.rs:12:5)"
+    );
+    assert!(lines[MAX_ANNOTATIONS_PER_LEVEL].starts_with("::warning file=src/lib.rs,"));
+}